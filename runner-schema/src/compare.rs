@@ -0,0 +1,335 @@
+use std::fmt::Display;
+
+use crate::non_negative::NonNegative;
+
+/// How a test case's stdout is compared against its expected output.
+#[derive(
+    Debug, Clone, Copy, Default, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq,
+)]
+pub enum CompareMode {
+    /// Byte for byte.
+    #[default]
+    Exact,
+    /// Line by line, ignoring whitespace at the end of each line
+    /// and empty lines at the end of the output.
+    IgnoreTrailingWhitespace,
+    /// Whitespace separated tokens.
+    Tokens,
+    /// Whitespace separated tokens, where tokens that both parse as numbers
+    /// match if they are within either epsilon of each other.
+    Float {
+        absolute_epsilon: Epsilon,
+        relative_epsilon: Epsilon,
+    },
+    /// Whitespace separated tokens, ignoring case.
+    CaseInsensitive,
+}
+
+/// A floating point tolerance.
+pub type Epsilon = NonNegative;
+
+/// Where the actual output first diverged from the expected output.
+#[derive(Debug, Clone, thiserror::Error, PartialEq, Eq)]
+pub enum Mismatch {
+    #[error("line {line}: expected {expected}, found {found}")]
+    Line {
+        line: usize,
+        expected: Snippet,
+        found: Snippet,
+    },
+    #[error("expected {expected} lines, found {found}")]
+    LineCount { expected: usize, found: usize },
+    #[error("token {token}: expected {expected}, found {found}")]
+    Token {
+        token: usize,
+        expected: Snippet,
+        found: Snippet,
+    },
+    #[error("expected {expected} tokens, found {found}")]
+    TokenCount { expected: usize, found: usize },
+    /// Output that is not valid UTF-8 differed at byte `offset`, counting from 0.
+    #[error("byte {offset}: expected {expected:?}, found {found:?}")]
+    Byte {
+        offset: usize,
+        expected: Option<u8>,
+        found: Option<u8>,
+    },
+}
+
+/// A piece of output quoted in a [`Mismatch`], truncated to keep hints short.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet(String);
+
+impl Snippet {
+    const MAX_CHARS: usize = 64;
+
+    fn new(s: &str) -> Self {
+        match s.char_indices().nth(Self::MAX_CHARS) {
+            Some((index, _)) => Self(format!("{}...", &s[..index])),
+            None => Self(s.to_string()),
+        }
+    }
+}
+
+impl Display for Snippet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl CompareMode {
+    /// Compares raw output, as text if both sides are valid UTF-8.
+    ///
+    /// Anything else is compared byte for byte whatever the mode,
+    /// since lines and tokens mean nothing for binary output.
+    pub fn compare_bytes(&self, expected: &[u8], actual: &[u8]) -> Result<(), Mismatch> {
+        if let (Ok(expected), Ok(actual)) =
+            (std::str::from_utf8(expected), std::str::from_utf8(actual))
+        {
+            return self.compare(expected, actual);
+        }
+
+        let offset = expected
+            .iter()
+            .zip(actual)
+            .position(|(e, a)| e != a)
+            .unwrap_or(expected.len().min(actual.len()));
+        if offset == expected.len() && offset == actual.len() {
+            return Ok(());
+        }
+        Err(Mismatch::Byte {
+            offset,
+            expected: expected.get(offset).copied(),
+            found: actual.get(offset).copied(),
+        })
+    }
+
+    pub fn compare(&self, expected: &str, actual: &str) -> Result<(), Mismatch> {
+        match self {
+            CompareMode::Exact => {
+                if expected == actual {
+                    return Ok(());
+                }
+                compare_lines(expected.split('\n'), actual.split('\n'))
+            }
+            CompareMode::IgnoreTrailingWhitespace => {
+                compare_lines(trimmed_lines(expected), trimmed_lines(actual))
+            }
+            CompareMode::Tokens => compare_tokens(expected, actual, |e, a| e == a),
+            CompareMode::Float {
+                absolute_epsilon,
+                relative_epsilon,
+            } => compare_tokens(expected, actual, |e, a| {
+                if e == a {
+                    return true;
+                }
+                let (Ok(e), Ok(a)) = (e.parse::<f64>(), a.parse::<f64>()) else {
+                    return false;
+                };
+                let diff = (e - a).abs();
+                diff <= absolute_epsilon.as_f64() || diff <= relative_epsilon.as_f64() * e.abs()
+            }),
+            CompareMode::CaseInsensitive => compare_tokens(expected, actual, |e, a| {
+                e.to_lowercase() == a.to_lowercase()
+            }),
+        }
+    }
+}
+
+fn trimmed_lines(s: &str) -> impl Iterator<Item = &str> {
+    s.trim_end().lines().map(str::trim_end)
+}
+
+fn compare_lines<'a>(
+    expected: impl Iterator<Item = &'a str>,
+    actual: impl Iterator<Item = &'a str>,
+) -> Result<(), Mismatch> {
+    let expected = expected.collect::<Vec<_>>();
+    let actual = actual.collect::<Vec<_>>();
+
+    if let Some((index, (e, a))) = expected
+        .iter()
+        .zip(&actual)
+        .enumerate()
+        .find(|(_, (e, a))| e != a)
+    {
+        return Err(Mismatch::Line {
+            line: index + 1,
+            expected: Snippet::new(e),
+            found: Snippet::new(a),
+        });
+    }
+
+    if expected.len() != actual.len() {
+        return Err(Mismatch::LineCount {
+            expected: expected.len(),
+            found: actual.len(),
+        });
+    }
+
+    Ok(())
+}
+
+fn compare_tokens(
+    expected: &str,
+    actual: &str,
+    eq: impl Fn(&str, &str) -> bool,
+) -> Result<(), Mismatch> {
+    let expected = expected.split_whitespace().collect::<Vec<_>>();
+    let actual = actual.split_whitespace().collect::<Vec<_>>();
+
+    if let Some((index, (e, a))) = expected
+        .iter()
+        .zip(&actual)
+        .enumerate()
+        .find(|(_, (e, a))| !eq(e, a))
+    {
+        return Err(Mismatch::Token {
+            token: index + 1,
+            expected: Snippet::new(e),
+            found: Snippet::new(a),
+        });
+    }
+
+    if expected.len() != actual.len() {
+        return Err(Mismatch::TokenCount {
+            expected: expected.len(),
+            found: actual.len(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float(absolute: f64, relative: f64) -> CompareMode {
+        CompareMode::Float {
+            absolute_epsilon: Epsilon::new(absolute).unwrap(),
+            relative_epsilon: Epsilon::new(relative).unwrap(),
+        }
+    }
+
+    #[test]
+    fn exact() {
+        assert_eq!(CompareMode::Exact.compare("1\n2\n", "1\n2\n"), Ok(()));
+        assert_eq!(
+            CompareMode::Exact.compare("1\n2\n", "1\n3\n"),
+            Err(Mismatch::Line {
+                line: 2,
+                expected: Snippet::new("2"),
+                found: Snippet::new("3"),
+            })
+        );
+        assert_eq!(
+            CompareMode::Exact.compare("1\n2\n", "1\n2"),
+            Err(Mismatch::LineCount {
+                expected: 3,
+                found: 2
+            })
+        );
+        assert!(CompareMode::Exact.compare("1 \n", "1\n").is_err());
+    }
+
+    #[test]
+    fn ignore_trailing_whitespace() {
+        let mode = CompareMode::IgnoreTrailingWhitespace;
+        assert_eq!(mode.compare("1\n2\n", "1  \n2\n\n\n"), Ok(()));
+        assert_eq!(mode.compare("1\n2", "1\r\n2\r\n"), Ok(()));
+        assert_eq!(
+            mode.compare("1\n2\n", "1\n"),
+            Err(Mismatch::LineCount {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert!(mode.compare("1 2\n", "1  2\n").is_err());
+    }
+
+    #[test]
+    fn tokens() {
+        let mode = CompareMode::Tokens;
+        assert_eq!(mode.compare("1 2\n3\n", "1\n2 3"), Ok(()));
+        assert_eq!(
+            mode.compare("1 2 3", "1 2 4"),
+            Err(Mismatch::Token {
+                token: 3,
+                expected: Snippet::new("3"),
+                found: Snippet::new("4"),
+            })
+        );
+        assert_eq!(
+            mode.compare("1 2 3", "1 2 3 4"),
+            Err(Mismatch::TokenCount {
+                expected: 3,
+                found: 4
+            })
+        );
+    }
+
+    #[test]
+    fn float_epsilon() {
+        assert_eq!(float(1e-6, 0.0).compare("0.1 2", "0.1000001 2"), Ok(()));
+        assert!(float(1e-6, 0.0).compare("0.1", "0.10001").is_err());
+        assert_eq!(float(0.0, 1e-3).compare("1000", "1000.5"), Ok(()));
+        assert!(float(0.0, 1e-3).compare("1000", "1002").is_err());
+        assert!(float(1.0, 1.0).compare("abc", "abd").is_err());
+        assert_eq!(float(0.0, 0.0).compare("abc", "abc"), Ok(()));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let mode = CompareMode::CaseInsensitive;
+        assert_eq!(mode.compare("YES\nNo", "yes no\n"), Ok(()));
+        assert!(mode.compare("YES", "YEP").is_err());
+    }
+
+    #[test]
+    fn mismatch_display() {
+        let long = "a".repeat(100);
+        let mismatch = CompareMode::Exact.compare(&long, "b").unwrap_err();
+        assert_eq!(
+            mismatch.to_string(),
+            format!(r#"line 1: expected "{}...", found "b""#, "a".repeat(64))
+        );
+    }
+
+    #[test]
+    fn compare_bytes() {
+        assert!(CompareMode::Tokens.compare_bytes(b"1 2\n", b"1  2").is_ok());
+        assert!(
+            CompareMode::Tokens
+                .compare_bytes(&[0xff, b' '], &[0xff, b' '])
+                .is_ok()
+        );
+        assert_eq!(
+            CompareMode::Tokens.compare_bytes(&[0xff, b' '], &[0xff]),
+            Err(Mismatch::Byte {
+                offset: 1,
+                expected: Some(b' '),
+                found: None,
+            })
+        );
+        assert_eq!(
+            CompareMode::Exact.compare_bytes(&[0xfe], &[0xff]),
+            Err(Mismatch::Byte {
+                offset: 0,
+                expected: Some(0xfe),
+                found: Some(0xff),
+            })
+        );
+    }
+
+    #[test]
+    fn epsilon() {
+        assert!(Epsilon::new(f64::NAN).is_none());
+        assert!(Epsilon::new(-1.0).is_none());
+        assert!(serde_json::from_str::<Epsilon>("-1.0").is_err());
+        let mode: CompareMode =
+            serde_json::from_str(r#"{"Float":{"absolute_epsilon":1e-6,"relative_epsilon":0}}"#)
+                .unwrap();
+        assert_eq!(mode, float(1e-6, 0.0));
+    }
+}
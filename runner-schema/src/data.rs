@@ -0,0 +1,110 @@
+use std::borrow::Cow;
+
+use base64::Engine;
+
+/// Bytes carried in JSON, either as a plain string or base64 encoded as `{"base64": "..."}`
+/// for anything that is not valid UTF-8.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Data {
+    Text(String),
+    Base64 { base64: String },
+}
+
+impl Data {
+    /// `bytes` as [`Data::Text`] if they are valid UTF-8, base64 encoded otherwise.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) => Data::Text(text),
+            Err(err) => Data::Base64 {
+                base64: base64::engine::general_purpose::STANDARD.encode(err.as_bytes()),
+            },
+        }
+    }
+
+    /// The bytes, decoded if they are base64 encoded.
+    pub fn to_bytes(&self) -> Result<Cow<'_, [u8]>, base64::DecodeError> {
+        match self {
+            Data::Text(text) => Ok(Cow::Borrowed(text.as_bytes())),
+            Data::Base64 { base64 } => base64::engine::general_purpose::STANDARD
+                .decode(base64)
+                .map(Cow::Owned),
+        }
+    }
+
+    /// The bytes, decoded if they are base64 encoded, without copying plain text.
+    pub fn into_bytes(self) -> Result<Vec<u8>, base64::DecodeError> {
+        match self {
+            Data::Text(text) => Ok(text.into_bytes()),
+            Data::Base64 { base64 } => base64::engine::general_purpose::STANDARD.decode(base64),
+        }
+    }
+}
+
+impl Default for Data {
+    fn default() -> Self {
+        Data::Text(String::new())
+    }
+}
+
+impl From<String> for Data {
+    fn from(text: String) -> Self {
+        Data::Text(text)
+    }
+}
+
+impl From<&str> for Data {
+    fn from(text: &str) -> Self {
+        Data::Text(text.to_string())
+    }
+}
+
+/// What a program wrote to stdout or stderr.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct CapturedOutput {
+    /// Whether the output was valid UTF-8, and so is given as [`Data::Text`].
+    pub utf8: bool,
+    pub data: Data,
+}
+
+impl CapturedOutput {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        let data = Data::from_bytes(bytes);
+        Self {
+            utf8: matches!(data, Data::Text(_)),
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = CapturedOutput::new(b"1 2\n".to_vec());
+        assert!(text.utf8);
+        assert_eq!(serde_json::to_string(&text.data).unwrap(), r#""1 2\n""#);
+
+        let binary = CapturedOutput::new(vec![0xff, 0x00]);
+        assert!(!binary.utf8);
+        assert_eq!(
+            serde_json::to_string(&binary.data).unwrap(),
+            r#"{"base64":"/wA="}"#
+        );
+        assert_eq!(binary.data.to_bytes().unwrap().as_ref(), [0xff, 0x00]);
+        assert_eq!(binary.data.clone().into_bytes().unwrap(), [0xff, 0x00]);
+
+        let data: Data = serde_json::from_str(r#"{"base64":"/wA="}"#).unwrap();
+        assert_eq!(data, binary.data);
+        let data: Data = serde_json::from_str(r#""plain""#).unwrap();
+        assert_eq!(data, Data::from("plain"));
+        assert!(
+            serde_json::from_str::<Data>(r#"{"base64":"!"}"#)
+                .unwrap()
+                .to_bytes()
+                .is_err()
+        );
+    }
+}
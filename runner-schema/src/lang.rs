@@ -1,25 +1,26 @@
+use std::fmt::Display;
+
+/// The id of a language defined in the runner's language registry, such as `rust182`.
+///
+/// Any id can be sent, the runner rejects those it has no definition for.
 #[derive(
-    Debug,
-    Clone,
-    Copy,
-    PartialEq,
-    Eq,
-    PartialOrd,
-    Ord,
-    Hash,
-    more_convert::EnumRepr,
-    more_convert::EnumArray,
-    more_convert::VariantName,
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
 )]
-#[repr(u32)]
-#[enum_repr(serde)]
-#[variant_name(rename_all = "camelCase")]
-pub enum Language {
-    Rust1_82 = 1,
-    Go1_23 = 2,
-    Python3_13 = 3,
+#[serde(transparent)]
+pub struct Language(String);
+
+impl Language {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
-impl enum_table::Enumable for Language {
-    const VARIANTS: &'static [Self] = Self::VARIANTS;
+impl Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
 }
@@ -1,7 +1,11 @@
 mod lang;
 pub use lang::Language;
 
+pub mod compare;
+pub mod data;
 pub mod memory;
+pub mod non_negative;
+pub mod score;
 pub mod state;
 pub mod time;
 pub mod web;
@@ -0,0 +1,77 @@
+/// A finite, non negative `f64`, which unlike a bare `f64` is `Eq` and `Hash`.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+#[repr(transparent)]
+pub struct NonNegative(f64);
+
+impl core::fmt::Debug for NonNegative {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_tuple("NonNegative").field(&self.0).finish()
+    }
+}
+
+impl NonNegative {
+    /// `value` unless it is negative, infinite or NaN, with -0.0 taken as 0.0.
+    pub fn new(value: f64) -> Option<Self> {
+        // Adding 0.0 turns -0.0 into 0.0, whose bits differ though the two compare equal.
+        (value.is_finite() && value >= 0.0).then_some(Self(value + 0.0))
+    }
+
+    pub const fn as_f64(&self) -> f64 {
+        self.0
+    }
+}
+
+// `new` rejects NaN and normalises -0.0, so the bit pattern is a valid identity.
+impl Eq for NonNegative {}
+
+impl core::hash::Hash for NonNegative {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for NonNegative {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = f64::deserialize(deserializer)?;
+        NonNegative::new(value).ok_or_else(|| {
+            serde::de::Error::custom(format!("{value} must be finite and non negative"))
+        })
+    }
+}
+
+impl serde::Serialize for NonNegative {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_f64(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasher, RandomState};
+
+    use super::*;
+
+    #[test]
+    fn negative_zero() {
+        let zero = NonNegative::new(0.0).unwrap();
+        let negative_zero = NonNegative::new(-0.0).unwrap();
+        assert_eq!(zero, negative_zero);
+        assert!(negative_zero.as_f64().is_sign_positive());
+
+        let state = RandomState::new();
+        assert_eq!(state.hash_one(zero), state.hash_one(negative_zero));
+        assert_eq!(
+            serde_json::from_str::<NonNegative>("-0.0")
+                .unwrap()
+                .as_f64()
+                .to_bits(),
+            0.0f64.to_bits()
+        );
+    }
+}
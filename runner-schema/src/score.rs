@@ -0,0 +1,4 @@
+use crate::non_negative::NonNegative;
+
+/// A score reported by a checker.
+pub type Score = NonNegative;
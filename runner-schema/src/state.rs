@@ -1,28 +1,344 @@
-use crate::{memory::Memory, time::MsTime};
+use crate::{data::CapturedOutput, memory::Memory, score::Score, time::MsTime};
 
 #[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub enum RunnerState {
+    /// The program exited normally and the test case had no expected output.
     Success {
-        stdout: String,
+        stdout: CapturedOutput,
 
         max_memory_usage: Memory,
         ms_time_elapsed: MsTime,
+        ms_cpu_time: MsTime,
+    },
+    /// The program's stdout matched the expected output, or the checker accepted it.
+    Accepted {
+        max_memory_usage: Memory,
+        ms_time_elapsed: MsTime,
+        ms_cpu_time: MsTime,
+
+        checker: Option<CheckerReport>,
+    },
+    /// The program's stdout did not match the expected output, or the checker rejected it.
+    WrongAnswer {
+        stdout: CapturedOutput,
+        diff_hint: String,
+
+        max_memory_usage: Memory,
+        ms_time_elapsed: MsTime,
+        ms_cpu_time: MsTime,
+
+        checker: Option<CheckerReport>,
+    },
+    /// The checker awarded partial points.
+    PartiallyAccepted {
+        stdout: CapturedOutput,
+        score: Score,
+
+        max_memory_usage: Memory,
+        ms_time_elapsed: MsTime,
+        ms_cpu_time: MsTime,
+
+        checker: CheckerReport,
+    },
+    /// No test case of a run failed, but some were only partially accepted,
+    /// see [`RunnerState::aggregate`]. Their scores are in the per test case states,
+    /// as only the caller knows what a fully accepted test case is worth.
+    Partial {
+        max_memory_usage: Memory,
+        ms_time_elapsed: MsTime,
+        ms_cpu_time: MsTime,
+    },
+    /// The checker or interactor failed to compile, crashed or reported a failure of its own.
+    CheckerError {
+        message: String,
     },
     RuntimeError {
-        stderr: String,
+        stderr: CapturedOutput,
         exit_code: i32,
+        signal: Option<Signal>,
+        kind: RuntimeErrorKind,
 
         max_memory_usage: Memory,
         ms_time_elapsed: MsTime,
+        ms_cpu_time: MsTime,
     },
     Timeout {
         ms_time_elapsed: MsTime,
+        ms_cpu_time: MsTime,
     },
     MemoryLimit {
         max_memory_usage: Memory,
     },
+    /// The program wrote more than [`crate::web::RunnerRequest::output_limit`]
+    /// to stdout or stderr and was killed. Both are cut off at the limit.
+    OutputLimitExceeded {
+        stdout: CapturedOutput,
+        stderr: CapturedOutput,
+    },
     CompileError {
         stderr: String,
     },
+    /// The compiler ran past the runner's compile time limit.
+    CompileTimeout {
+        ms_time_elapsed: MsTime,
+    },
+    /// The compiler ran past the runner's compile memory limit.
+    CompileMemoryLimit {
+        max_memory_usage: Memory,
+    },
+    /// The run was stopped before it finished.
+    Cancelled,
     InternalError,
 }
+
+/// What a checker printed and how much it used, kept apart from the contestant's usage.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct CheckerReport {
+    pub message: String,
+
+    pub max_memory_usage: Memory,
+    pub ms_time_elapsed: MsTime,
+    pub ms_cpu_time: MsTime,
+}
+
+/// A signal that terminated a program, e.g. `SIGSEGV` (11).
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Signal {
+    pub number: i32,
+    pub name: String,
+}
+
+/// Why a program ended with a [`RunnerState::RuntimeError`].
+#[derive(Debug, Clone, Copy, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    /// Exited with a non zero exit code.
+    NonZeroExit,
+    /// `SIGSEGV` or `SIGBUS`.
+    SegmentationFault,
+    /// `SIGFPE`, e.g. an integer division by zero.
+    FloatingPointException,
+    /// `SIGABRT`, e.g. a failed assertion or a panic with `panic = "abort"`.
+    Abort,
+    /// Killed by the sandbox for running into a time, memory or file size limit.
+    KilledByLimit,
+    /// Any other signal.
+    Signaled,
+}
+
+/// How much compiling the submission used.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct CompileReport {
+    pub max_memory_usage: Memory,
+    pub ms_time_elapsed: MsTime,
+    pub ms_cpu_time: MsTime,
+}
+
+/// The exchange between a contestant and an interactor, in the order it was read.
+#[derive(Debug, Clone, Default, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+    /// Whether recording stopped early because the exchange was too large.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TranscriptEntry {
+    pub from: Party,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Copy, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum Party {
+    Contestant,
+    Interactor,
+}
+
+impl RunnerState {
+    /// Whether the test case passed, i.e. [`RunnerState::Success`] or [`RunnerState::Accepted`].
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            RunnerState::Success { .. } | RunnerState::Accepted { .. }
+        )
+    }
+
+    /// Folds per test case states into a single verdict.
+    ///
+    /// The first state that neither succeeded nor was partially accepted wins.
+    /// Failing that, the result is [`RunnerState::Partial`] if any case was partially
+    /// accepted, and the last case's kind of success otherwise, without per case
+    /// checker reports. Either way it carries the peak memory and time over all cases.
+    /// Returns `None` for an empty slice.
+    pub fn aggregate(test_cases: &[RunnerState]) -> Option<RunnerState> {
+        if let Some(failed) = test_cases.iter().find(|state| {
+            !state.is_success() && !matches!(state, RunnerState::PartiallyAccepted { .. })
+        }) {
+            return Some(failed.clone());
+        }
+
+        let mut peak_memory = Memory::new_bytes(0);
+        let mut peak_time = MsTime::new_ms(0);
+        let mut peak_cpu_time = MsTime::new_ms(0);
+        let mut partial = false;
+        for state in test_cases {
+            if let RunnerState::Success {
+                max_memory_usage,
+                ms_time_elapsed,
+                ms_cpu_time,
+                ..
+            }
+            | RunnerState::Accepted {
+                max_memory_usage,
+                ms_time_elapsed,
+                ms_cpu_time,
+                ..
+            }
+            | RunnerState::PartiallyAccepted {
+                max_memory_usage,
+                ms_time_elapsed,
+                ms_cpu_time,
+                ..
+            } = state
+            {
+                peak_memory = peak_memory.max(*max_memory_usage);
+                peak_time = peak_time.max(*ms_time_elapsed);
+                peak_cpu_time = peak_cpu_time.max(*ms_cpu_time);
+            }
+            partial |= matches!(state, RunnerState::PartiallyAccepted { .. });
+        }
+
+        if partial {
+            return Some(RunnerState::Partial {
+                max_memory_usage: peak_memory,
+                ms_time_elapsed: peak_time,
+                ms_cpu_time: peak_cpu_time,
+            });
+        }
+
+        Some(match test_cases.last()? {
+            RunnerState::Success { stdout, .. } => RunnerState::Success {
+                stdout: stdout.clone(),
+                max_memory_usage: peak_memory,
+                ms_time_elapsed: peak_time,
+                ms_cpu_time: peak_cpu_time,
+            },
+            _ => RunnerState::Accepted {
+                max_memory_usage: peak_memory,
+                ms_time_elapsed: peak_time,
+                ms_cpu_time: peak_cpu_time,
+                checker: None,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn success(stdout: &str, megabytes: u64, ms: u64, cpu_ms: u64) -> RunnerState {
+        RunnerState::Success {
+            stdout: CapturedOutput::new(stdout.as_bytes().to_vec()),
+            max_memory_usage: Memory::new_megabytes(megabytes),
+            ms_time_elapsed: MsTime::new_ms(ms),
+            ms_cpu_time: MsTime::new_ms(cpu_ms),
+        }
+    }
+
+    fn accepted(megabytes: u64, ms: u64, cpu_ms: u64) -> RunnerState {
+        RunnerState::Accepted {
+            max_memory_usage: Memory::new_megabytes(megabytes),
+            ms_time_elapsed: MsTime::new_ms(ms),
+            ms_cpu_time: MsTime::new_ms(cpu_ms),
+            checker: None,
+        }
+    }
+
+    fn partially_accepted(score: f64, message: &str, megabytes: u64, ms: u64) -> RunnerState {
+        RunnerState::PartiallyAccepted {
+            stdout: CapturedOutput::new(message.as_bytes().to_vec()),
+            score: Score::new(score).unwrap(),
+            max_memory_usage: Memory::new_megabytes(megabytes),
+            ms_time_elapsed: MsTime::new_ms(ms),
+            ms_cpu_time: MsTime::new_ms(ms),
+            checker: CheckerReport {
+                message: message.to_string(),
+                max_memory_usage: Memory::new_megabytes(1),
+                ms_time_elapsed: MsTime::new_ms(1),
+                ms_cpu_time: MsTime::new_ms(1),
+            },
+        }
+    }
+
+    #[test]
+    fn aggregate_empty() {
+        assert_eq!(RunnerState::aggregate(&[]), None);
+    }
+
+    #[test]
+    fn aggregate_all_success() {
+        let states = [
+            success("a", 3, 10, 5),
+            success("b", 1, 30, 2),
+            success("c", 2, 20, 20),
+        ];
+        assert_eq!(
+            RunnerState::aggregate(&states),
+            Some(success("c", 3, 30, 20))
+        );
+    }
+
+    #[test]
+    fn aggregate_all_accepted() {
+        let states = [
+            accepted(3, 10, 10),
+            success("b", 1, 30, 5),
+            accepted(2, 20, 15),
+        ];
+        assert_eq!(RunnerState::aggregate(&states), Some(accepted(3, 30, 15)));
+    }
+
+    #[test]
+    fn aggregate_first_failure() {
+        let wrong_answer = RunnerState::WrongAnswer {
+            stdout: CapturedOutput::new(b"2".to_vec()),
+            diff_hint: "line 1: expected \"1\", found \"2\"".to_string(),
+            max_memory_usage: Memory::new_megabytes(1),
+            ms_time_elapsed: MsTime::new_ms(10),
+            ms_cpu_time: MsTime::new_ms(10),
+            checker: None,
+        };
+        let states = [
+            accepted(1, 10, 10),
+            wrong_answer.clone(),
+            RunnerState::InternalError,
+        ];
+        assert_eq!(RunnerState::aggregate(&states), Some(wrong_answer));
+    }
+
+    #[test]
+    fn aggregate_partially_accepted() {
+        let states = [
+            partially_accepted(0.5, "a", 1, 10),
+            accepted(3, 5, 5),
+            partially_accepted(1.5, "b", 2, 20),
+        ];
+        assert_eq!(
+            RunnerState::aggregate(&states),
+            Some(RunnerState::Partial {
+                max_memory_usage: Memory::new_megabytes(3),
+                ms_time_elapsed: MsTime::new_ms(20),
+                ms_cpu_time: MsTime::new_ms(20),
+            })
+        );
+
+        let failed = [
+            partially_accepted(1.0, "a", 1, 10),
+            RunnerState::InternalError,
+        ];
+        assert_eq!(
+            RunnerState::aggregate(&failed),
+            Some(RunnerState::InternalError)
+        );
+    }
+}
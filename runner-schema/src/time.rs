@@ -45,19 +45,127 @@ impl MsTime {
         Self(self.0 + ms)
     }
 
+    /// Multiplies the time by `factor`, rounding to the nearest millisecond.
+    pub fn scale(&self, factor: f64) -> Self {
+        Self((self.0 as f64 * factor).round() as u64)
+    }
+
+    /// Parses GNU time's elapsed time, `[hours:]minutes:seconds.fraction`.
     pub fn from_str_mm_ss_ms(s: &str) -> Option<Self> {
-        let mut parts = s.split(':');
+        let mut parts = s.rsplit(':');
+        let seconds = Self::from_str_seconds(parts.next()?)?;
         let minutes = parts.next()?.parse::<u64>().ok()?;
-        let ss_ms = parts.next()?.split('.').collect::<Vec<_>>();
-        if ss_ms.len() != 2 {
+        let hours = match parts.next() {
+            Some(hours) => hours.parse::<u64>().ok()?,
+            None => 0,
+        };
+        if parts.next().is_some() {
             return None;
         }
-        let seconds = ss_ms[0].parse::<u64>().ok()?;
-        let ms = ss_ms[1].parse::<u64>().ok()?;
-        if ms > 999 {
+        Some(seconds.add_seconds((hours * 60 + minutes) * 60))
+    }
+
+    /// Parses seconds with up to three fractional digits, e.g. `1.5` or `0.25`.
+    pub fn from_str_seconds(s: &str) -> Option<Self> {
+        let (seconds, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let seconds = seconds.parse::<u64>().ok()?;
+        if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
             return None;
         }
-        let total_seconds = minutes * 60 + seconds;
-        Some(Self::new(total_seconds, ms))
+        let ms = format!("{fraction:0<3}").parse::<u64>().ok()?;
+        Some(Self::new(seconds, ms))
+    }
+}
+
+/// Which clock [`crate::web::RunnerRequest::ms_time_limit`] is measured against.
+#[derive(
+    Debug, Clone, Copy, Default, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq,
+)]
+pub enum TimeLimitMode {
+    /// Wall clock time.
+    #[default]
+    Wall,
+    /// User plus system CPU time. Wall clock time is only capped to stop programs
+    /// that sleep or block, at the wall time limit if given or twice the time limit.
+    Cpu,
+    /// Both CPU time and wall clock time, the latter against the wall time limit
+    /// if given or the time limit itself.
+    Both,
+}
+
+impl TimeLimitMode {
+    /// The CPU time a run may use, if it is limited at all.
+    pub fn cpu_time_limit(&self, ms_time_limit: MsTime) -> Option<MsTime> {
+        match self {
+            TimeLimitMode::Wall => None,
+            TimeLimitMode::Cpu | TimeLimitMode::Both => Some(ms_time_limit),
+        }
+    }
+
+    /// The wall clock time a run may take.
+    pub fn wall_time_limit(
+        &self,
+        ms_time_limit: MsTime,
+        ms_wall_time_limit: Option<MsTime>,
+    ) -> MsTime {
+        match self {
+            TimeLimitMode::Wall => ms_time_limit,
+            TimeLimitMode::Cpu => {
+                ms_wall_time_limit.unwrap_or(MsTime::new_ms(ms_time_limit.as_ms() * 2))
+            }
+            TimeLimitMode::Both => ms_wall_time_limit.unwrap_or(ms_time_limit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_mm_ss_ms() {
+        assert_eq!(
+            MsTime::from_str_mm_ss_ms("0:01.50"),
+            Some(MsTime::new(1, 500))
+        );
+        assert_eq!(
+            MsTime::from_str_mm_ss_ms("2:03.04"),
+            Some(MsTime::new(123, 40))
+        );
+        assert_eq!(
+            MsTime::from_str_mm_ss_ms("1:00:00.00"),
+            Some(MsTime::new_seconds(3600))
+        );
+        assert_eq!(MsTime::from_str_mm_ss_ms("1.00"), None);
+        assert_eq!(MsTime::from_str_mm_ss_ms("0:1.0000"), None);
+    }
+
+    #[test]
+    fn from_str_seconds() {
+        assert_eq!(MsTime::from_str_seconds("0.25"), Some(MsTime::new_ms(250)));
+        assert_eq!(MsTime::from_str_seconds("1.5"), Some(MsTime::new_ms(1500)));
+        assert_eq!(MsTime::from_str_seconds("2"), Some(MsTime::new_seconds(2)));
+        assert_eq!(MsTime::from_str_seconds("0.123"), Some(MsTime::new_ms(123)));
+        assert_eq!(MsTime::from_str_seconds("0.1234"), None);
+        assert_eq!(MsTime::from_str_seconds("0.-1"), None);
+        assert_eq!(MsTime::from_str_seconds(""), None);
+    }
+
+    #[test]
+    fn time_limit_mode() {
+        let limit = MsTime::new_seconds(2);
+        let ceiling = Some(MsTime::new_seconds(10));
+        assert_eq!(TimeLimitMode::Wall.cpu_time_limit(limit), None);
+        assert_eq!(TimeLimitMode::Wall.wall_time_limit(limit, ceiling), limit);
+        assert_eq!(TimeLimitMode::Cpu.cpu_time_limit(limit), Some(limit));
+        assert_eq!(
+            TimeLimitMode::Cpu.wall_time_limit(limit, None),
+            MsTime::new_seconds(4)
+        );
+        assert_eq!(
+            TimeLimitMode::Both.wall_time_limit(limit, ceiling),
+            MsTime::new_seconds(10)
+        );
+        assert_eq!(TimeLimitMode::Both.wall_time_limit(limit, None), limit);
     }
 }
@@ -1,15 +1,265 @@
-use crate::{Language, memory::Memory, state::RunnerState, time::MsTime};
+use std::collections::BTreeMap;
+
+use crate::{
+    Language,
+    compare::CompareMode,
+    data::Data,
+    memory::Memory,
+    state::{CompileReport, RunnerState, Transcript},
+    time::{MsTime, TimeLimitMode},
+};
 
 #[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct RunnerRequest {
+    pub lang: Language,
+    /// The entry point, written to the file its language names.
+    /// May be left empty when `files` has that file.
+    pub code: String,
+    /// Files placed around the entry point before compiling.
+    #[serde(default)]
+    pub files: Option<SubmissionFiles>,
+    /// Files of the problem, like a grader and its headers, by paths relative to the
+    /// entry point. They are mounted read-only next to the submission, which may not
+    /// have files of the same paths, and switch its language to its grader commands.
+    #[serde(default)]
+    pub problem_files: BTreeMap<String, String>,
+    pub ms_time_limit: MsTime,
+    /// Which clock `ms_time_limit` is measured against.
+    #[serde(default)]
+    pub time_limit_mode: TimeLimitMode,
+    /// Caps wall clock time separately, see [`TimeLimitMode`].
+    pub ms_wall_time_limit: Option<MsTime>,
+    pub memory_limit: Memory,
+    pub test_cases: Vec<TestCase>,
+    /// Where the program reads its input from and writes its output to.
+    #[serde(default)]
+    pub io_mode: IoMode,
+    /// How stdout is checked against [`TestCase::expected_output`].
+    #[serde(default)]
+    pub compare_mode: CompareMode,
+    /// Judges stdout with a checker program instead of `compare_mode`.
+    pub checker: Option<Checker>,
+    /// Runs every test case interactively, ignoring `checker` and `compare_mode`.
+    pub interactor: Option<Interactor>,
+    /// Caps stdout and stderr of every run, and stderr of the compiler.
+    #[serde(default = "default_output_limit")]
+    pub output_limit: Memory,
+    /// Names of compile options to build the submission with,
+    /// out of the [`LanguageInfo::compile_options`] of its language.
+    #[serde(default)]
+    pub compile_options: Vec<String>,
+}
+
+/// The files of a multi-file submission, by paths relative to the entry point.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum SubmissionFiles {
+    /// Contents by path.
+    Map(BTreeMap<String, String>),
+    /// A base64 encoded tar archive.
+    Tar(String),
+    /// A base64 encoded zip archive.
+    Zip(String),
+}
+
+impl RunnerRequest {
+    pub fn cpu_time_limit(&self) -> Option<MsTime> {
+        self.time_limit_mode.cpu_time_limit(self.ms_time_limit)
+    }
+
+    pub fn wall_time_limit(&self) -> MsTime {
+        self.time_limit_mode
+            .wall_time_limit(self.ms_time_limit, self.ms_wall_time_limit)
+    }
+}
+
+fn default_output_limit() -> Memory {
+    Memory::new_megabytes(64)
+}
+
+/// A problem supplied checker, run testlib style as
+/// `<run command> input.txt output.txt answer.txt`.
+///
+/// Exit code 0 accepts, 1 and 2 reject, 7 awards the points given as the first
+/// token of stderr, and anything else is a checker failure. The rest of stderr
+/// is reported as the checker's message.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Checker {
+    pub lang: Language,
+    pub code: String,
+    pub ms_time_limit: MsTime,
+    pub memory_limit: Memory,
+}
+
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TestCase {
+    pub stdin: TestData,
+    pub expected_output: Option<TestData>,
+}
+
+/// How a program is given [`TestCase::stdin`] and where the output that is judged is read from.
+#[derive(Debug, Clone, Default, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum IoMode {
+    /// Piped to stdin and read from stdout.
+    #[default]
+    Stdio,
+    /// Placed in the file `input` of the working directory, with stdin left empty, and read
+    /// from the file `output` there, which takes the place of stdout in the response and is
+    /// capped by [`RunnerRequest::output_limit`] the same way. Not for interactive problems.
+    File { input: String, output: String },
+}
+
+/// Test data given inline, or as `{"sha256": "..."}` by the hash of what was uploaded
+/// to the runner with `PUT /testdata/{sha256}`.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum TestData {
+    Inline(Data),
+    Stored { sha256: String },
+}
+
+impl From<Data> for TestData {
+    fn from(data: Data) -> Self {
+        TestData::Inline(data)
+    }
+}
+
+/// An entry of the runner's test data store, as listed by `GET /testdata`.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TestDataInfo {
+    /// The lowercase hex SHA-256 of the data, which it is stored and referred to by.
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+/// A problem supplied interactor, run as `<run command> input.txt answer.txt`
+/// with its stdout piped to the contestant's stdin and the other way around.
+///
+/// `input.txt` holds [`TestCase::stdin`] and `answer.txt` holds
+/// [`TestCase::expected_output`]. The exit code is read the same way as a [`Checker`]'s.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Interactor {
     pub lang: Language,
     pub code: String,
     pub ms_time_limit: MsTime,
     pub memory_limit: Memory,
-    pub stdin: String,
+    /// Records what both sides wrote into [`RunnerResponse::transcripts`].
+    #[serde(default)]
+    pub transcript: bool,
 }
 
 #[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct RunnerResponse {
+    /// Aggregate verdict, see [`RunnerState::aggregate`].
+    /// Compile failures are reported here with `test_cases` left empty.
     pub state: RunnerState,
+    /// One state per [`RunnerRequest::test_cases`], in the same order.
+    pub test_cases: Vec<RunnerState>,
+    /// What compiling the submission used, `None` for languages without a compile step.
+    pub compile: Option<CompileReport>,
+    /// One transcript per test case when [`Interactor::transcript`] is set, otherwise empty.
+    pub transcripts: Vec<Transcript>,
+}
+
+impl RunnerResponse {
+    /// Reported when the runner itself failed, rather than the submission.
+    pub fn internal_error() -> Self {
+        Self {
+            state: RunnerState::InternalError,
+            test_cases: Vec::new(),
+            compile: None,
+            transcripts: Vec::new(),
+        }
+    }
+}
+
+/// Where a queued submission is in its run.
+#[derive(Debug, Clone, Copy, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum SubmissionStatus {
+    /// Waiting for a free worker.
+    Queued,
+    /// Compiling the submission, and the checker or interactor if any.
+    Compiling,
+    /// Running the test cases.
+    Running,
+    /// Done, with [`Submission::response`] set.
+    Finished,
+}
+
+/// Returned by `POST /submissions`.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct SubmissionCreated {
+    /// The ULID to poll `GET /submissions/{id}` with.
+    pub id: String,
+}
+
+/// Returned by `GET /submissions/{id}`.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Submission {
+    pub id: String,
+    pub status: SubmissionStatus,
+    /// Set once `status` is [`SubmissionStatus::Finished`].
+    pub response: Option<RunnerResponse>,
+}
+
+/// A language the runner accepts, as listed by `GET /languages`.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct LanguageInfo {
+    /// What [`RunnerRequest::lang`] is set to.
+    pub id: Language,
+    pub name: String,
+    /// What [`RunnerRequest::compile_options`] may hold.
+    pub compile_options: Vec<String>,
+}
+
+/// Returned by `GET /queue`.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct QueueStatus {
+    /// Submissions waiting for a worker.
+    pub queued: usize,
+    /// Submissions being compiled or run.
+    pub running: usize,
+    pub workers: usize,
+    /// How many submissions may wait before new ones are turned away.
+    pub capacity: usize,
+}
+
+/// Sent by `POST /run/stream` as server sent events, one per event.
+#[derive(Debug, Clone, Hash, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub enum RunEvent {
+    /// Always the first event, with the id `POST /submissions/{id}/cancel` takes.
+    Queued { id: String },
+    /// Compiling the submission, and the checker or interactor if any.
+    Compiling,
+    /// Started running test case `index`, counting from 0, of `total`.
+    Running { index: usize, total: usize },
+    /// Part of what test case `index` wrote to stdout, as soon as it was read,
+    /// with anything that is not valid UTF-8 replaced. The verdict carries the exact bytes.
+    Stdout { index: usize, data: String },
+    /// Part of what test case `index` wrote to stderr, as soon as it was read,
+    /// replaced the same way as [`RunEvent::Stdout`].
+    Stderr { index: usize, data: String },
+    /// The verdict of test case `index`.
+    TestCase { index: usize, state: RunnerState },
+    /// Always the last event.
+    Finished(RunnerResponse),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data() {
+        let stdin: TestData = serde_json::from_str(r#""1 2""#).unwrap();
+        assert_eq!(stdin, TestData::Inline(Data::from("1 2")));
+        let stdin: TestData = serde_json::from_str(r#"{"base64":"/wA="}"#).unwrap();
+        assert!(matches!(stdin, TestData::Inline(Data::Base64 { .. })));
+        let stdin: TestData = serde_json::from_str(r#"{"sha256":"ab"}"#).unwrap();
+        assert_eq!(
+            stdin,
+            TestData::Stored {
+                sha256: "ab".to_string()
+            }
+        );
+    }
 }
@@ -1,12 +1,12 @@
 #![allow(clippy::unwrap_used)]
 
-use more_convert::VariantName;
-
 pub const RUNNER_PATH: &str = "/runner";
 pub const RUNNING_PATH: &str = "/running";
 pub const NIX_STORE_PATH: &str = "/nix/store";
 pub const NIX_BIN: &str = "/global/bin";
 pub const PERMISSION_ID_STR: &str = "99999";
+pub const LANGUAGES_PATH: &str = "/languages.toml";
+pub const TESTDATA_PATH: &str = "/testdata";
 
 fn main() {
     println!("cargo:rustc-env=RUNNER_PATH={}", RUNNER_PATH);
@@ -14,14 +14,20 @@ fn main() {
     println!("cargo:rustc-env=NIX_STORE_PATH={}", NIX_STORE_PATH);
     println!("cargo:rustc-env=NIX_BIN={}", NIX_BIN);
     println!("cargo:rustc-env=PERMISSION_ID_STR={}", PERMISSION_ID_STR);
-
-    let builds = runner_schema::Language::VARIANTS
+    println!("cargo:rustc-env=LANGUAGES_PATH={}", LANGUAGES_PATH);
+    println!("cargo:rustc-env=TESTDATA_PATH={}", TESTDATA_PATH);
+
+    let languages = std::fs::read_to_string("languages.toml").unwrap();
+    let languages = languages.parse::<toml::Table>().unwrap();
+    let builds = languages["language"]
+        .as_array()
+        .unwrap()
         .iter()
         .map(|lang| {
-            let name = lang.variant_name();
+            let id = lang["id"].as_str().unwrap();
             format!(
                 "nix-build /default.nix -A {} --out-link {}/{}",
-                name, RUNNER_PATH, name
+                id, RUNNER_PATH, id
             )
         })
         .collect::<Vec<_>>();
@@ -40,10 +46,10 @@ RUN {mkdirs} && \\\n  {build}
 ",
     );
 
-    if let Ok(read_text) = std::fs::read("Dockerfile.build") {
-        if read_text == text.as_bytes() {
-            return;
-        }
+    if let Ok(read_text) = std::fs::read("Dockerfile.build")
+        && read_text == text.as_bytes()
+    {
+        return;
     }
 
     std::fs::write("Dockerfile.build", text).unwrap();
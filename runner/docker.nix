@@ -5,9 +5,29 @@ let
     name = "rustc182";
     paths = [ pkgs.rustc pkgs.gcc14 ];
   };
+
+  # The static glibc lets C and C++ submissions link with -static,
+  # found through LIBRARY_PATH set in languages.toml.
+  gcc14 = pkgs.buildEnv {
+    name = "gcc14";
+    paths = [ pkgs.gcc14 pkgs.glibc.static ];
+  };
 in
 {
   inherit rust182;
   go123 = pkgs.go_1_23;
   python313 = pkgs.python313;
+  pypy3 = pkgs.pypy3;
+  java21 = pkgs.jdk21_headless;
+  kotlin = pkgs.buildEnv {
+    name = "kotlin";
+    paths = [ pkgs.kotlin pkgs.jdk21_headless ];
+  };
+  node22 = pkgs.nodejs_22;
+  deno2 = pkgs.deno;
+  c11 = gcc14;
+  c17 = gcc14;
+  cpp17 = gcc14;
+  cpp20 = gcc14;
+  cpp23 = gcc14;
 }
@@ -0,0 +1,65 @@
+/// A set of CPUs, as the affinity mask of sched_setaffinity(2).
+#[derive(Clone, Copy)]
+pub struct CpuSet(libc::cpu_set_t);
+
+impl CpuSet {
+    /// `cpus`, leaving out any at or above [`libc::CPU_SETSIZE`].
+    pub fn new(cpus: impl IntoIterator<Item = usize>) -> Self {
+        // SAFETY: cpu_set_t is a plain bit set, for which all zeroes is the empty set,
+        // and CPU_SET only sets a bit, of a CPU within the set.
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for cpu in cpus
+                .into_iter()
+                .filter(|&cpu| cpu < libc::CPU_SETSIZE as usize)
+            {
+                libc::CPU_SET(cpu, &mut set);
+            }
+            Self(set)
+        }
+    }
+
+    /// The CPUs the calling thread may run on.
+    pub fn current() -> std::io::Result<Self> {
+        let mut set = Self::new([]);
+        // SAFETY: `set.0` is a valid cpu_set_t of the size passed along with it.
+        match unsafe { libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set.0) } {
+            0 => Ok(set),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
+    /// Restricts the calling thread to these CPUs.
+    ///
+    /// Only makes the system call, so it is async-signal-safe and can be done between
+    /// fork and exec.
+    pub fn apply(&self) -> std::io::Result<()> {
+        // SAFETY: `self.0` is a valid cpu_set_t of the size passed along with it.
+        match unsafe { libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &self.0) } {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
+    /// The CPUs in the set, in ascending order.
+    pub fn cpus(&self) -> Vec<usize> {
+        (0..libc::CPU_SETSIZE as usize)
+            // SAFETY: CPU_ISSET only reads a bit, indexing the set bounds checked.
+            .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &self.0) })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpus() {
+        assert_eq!(
+            CpuSet::new([3, 0, libc::CPU_SETSIZE as usize]).cpus(),
+            [0, 3]
+        );
+        assert!(!CpuSet::current().unwrap().cpus().is_empty());
+    }
+}
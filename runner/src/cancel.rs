@@ -0,0 +1,77 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::program::kill_process_group;
+
+/// Stops a run early by killing every sandbox it has running, now or later.
+///
+/// Clones share the same state, so the run can be cancelled from another thread.
+#[derive(Clone, Default)]
+pub struct Cancellation {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: bool,
+    /// Process groups of the sandboxes running right now.
+    sandboxes: HashSet<u32>,
+}
+
+impl Cancellation {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn cancel(&self) {
+        let mut inner = self.lock();
+        inner.cancelled = true;
+        for &pid in &inner.sandboxes {
+            kill_process_group(pid);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled
+    }
+
+    /// Tracks the sandbox led by `pid` until [`Cancellation::unregister`],
+    /// killing it right away if the run was already cancelled.
+    pub(crate) fn register(&self, pid: u32) {
+        let mut inner = self.lock();
+        if inner.cancelled {
+            kill_process_group(pid);
+        }
+        inner.sandboxes.insert(pid);
+    }
+
+    pub(crate) fn unregister(&self, pid: u32) {
+        self.lock().sandboxes.remove(&pid);
+    }
+}
+
+/// Cancels a run when dropped, which does nothing once it has finished.
+pub struct CancelOnDrop(pub Cancellation);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared() {
+        let cancellation = Cancellation::default();
+        let clone = cancellation.clone();
+        assert!(!clone.is_cancelled());
+        drop(CancelOnDrop(cancellation.clone()));
+        assert!(cancellation.is_cancelled());
+        assert!(clone.is_cancelled());
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use runner_schema::{memory::Memory, time::MsTime};
+
+use crate::{
+    env::CGROUP_PATH,
+    nsjail::NsJailBuilder,
+    time::{Meter, Usage},
+};
+
+/// Measures with cgroup v2, outside of anything the sandbox can write to.
+///
+/// Each meter owns a cgroup under [`CGROUP_PATH`] that nsjail is pointed at with
+/// `--cgroupv2_mount`, so the cgroup nsjail creates for the jailed process is nested in it.
+/// nsjail removes its own cgroup when the process exits, but the counters of the parent
+/// still include everything the child used.
+pub struct CGroup {
+    path: PathBuf,
+}
+
+impl Default for CGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CGroup {
+    const MEMORY_PEAK: &'static str = "memory.peak";
+    const MEMORY_EVENTS: &'static str = "memory.events";
+    const CPU_STAT: &'static str = "cpu.stat";
+    const SUBTREE_CONTROL: &'static str = "cgroup.subtree_control";
+    const KILL: &'static str = "cgroup.kill";
+
+    /// How many times removing a cgroup is tried while its killed processes exit.
+    const REMOVE_ATTEMPTS: u32 = 50;
+
+    pub fn new() -> Self {
+        Self {
+            path: Path::new(CGROUP_PATH).join(ulid::Ulid::new().to_string()),
+        }
+    }
+
+    /// Creates [`CGROUP_PATH`] and hands the memory controller down to the cgroups below it.
+    ///
+    /// Fails if a cgroup created below it lacks any file a run is measured with.
+    pub fn setup() -> std::io::Result<()> {
+        let root = Path::new(CGROUP_PATH);
+        std::fs::create_dir_all(root)?;
+        if let Some(parent) = root.parent() {
+            std::fs::write(parent.join(Self::SUBTREE_CONTROL), "+memory")?;
+        }
+        std::fs::write(root.join(Self::SUBTREE_CONTROL), "+memory")?;
+
+        // Left behind by a previous runner that did not get to clean up.
+        for entry in std::fs::read_dir(root)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                Self { path: entry.path() }.remove()?;
+            }
+        }
+
+        // memory.peak only exists since Linux 5.19.
+        let probe = Self::new();
+        std::fs::create_dir(&probe.path)?;
+        for name in [Self::MEMORY_PEAK, Self::MEMORY_EVENTS, Self::CPU_STAT] {
+            if !probe.path.join(name).is_file() {
+                return Err(std::io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("{name} is missing"),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Kills anything still running in the cgroup and removes it along with nsjail's.
+    fn remove(&self) -> std::io::Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        // Only there since Linux 5.14. Without it, nsjail having exited is relied on.
+        let _ = std::fs::write(self.path.join(Self::KILL), "1");
+
+        let mut attempts = 0;
+        loop {
+            match self.remove_dirs() {
+                Err(err)
+                    if err.kind() == ErrorKind::ResourceBusy
+                        && attempts < Self::REMOVE_ATTEMPTS =>
+                {
+                    attempts += 1;
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn remove_dirs(&self) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                std::fs::remove_dir(entry.path())?;
+            }
+        }
+        std::fs::remove_dir(&self.path)
+    }
+
+    fn read_file(&self, name: &str) -> std::io::Result<String> {
+        std::fs::read_to_string(self.path.join(name))
+    }
+}
+
+impl Meter for CGroup {
+    fn start(&self) -> std::io::Result<NsJailBuilder> {
+        // memory.peak and cpu.stat only ever grow, so every run gets a fresh cgroup.
+        self.remove()?;
+        std::fs::create_dir(&self.path)?;
+
+        let mut builder = NsJailBuilder::new();
+        builder.arg("--cgroupv2_mount").arg(&self.path);
+        Ok(builder)
+    }
+
+    fn read(&self, elapsed: MsTime) -> std::io::Result<Usage> {
+        let memory = parse_memory_peak(&self.read_file(Self::MEMORY_PEAK)?)?;
+        let cpu_time = parse_cpu_stat(&self.read_file(Self::CPU_STAT)?)?;
+        let oom_killed = parse_memory_events(&self.read_file(Self::MEMORY_EVENTS)?)?;
+
+        Ok(Usage {
+            memory,
+            time: elapsed,
+            cpu_time,
+            oom_killed,
+        })
+    }
+}
+
+impl Drop for CGroup {
+    fn drop(&mut self) {
+        if let Err(err) = self.remove() {
+            log::warn!("Failed to remove cgroup {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn parse_memory_peak(memory_peak: &str) -> std::io::Result<Memory> {
+    memory_peak
+        .trim()
+        .parse::<u64>()
+        .map(Memory::new_bytes)
+        .map_err(|_| invalid_data(format!("Invalid memory.peak: {memory_peak}")))
+}
+
+/// Reads `key` from a flat keyed file like `cpu.stat`, with one `key value` pair per line.
+fn keyed_value(file: &str, key: &str) -> std::io::Result<u64> {
+    file.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(' '))
+        .ok_or_else(|| invalid_data(format!("Missing {key}")))?
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| invalid_data(format!("Invalid {key}")))
+}
+
+fn parse_cpu_stat(cpu_stat: &str) -> std::io::Result<MsTime> {
+    keyed_value(cpu_stat, "usage_usec").map(|usec| MsTime::new_ms(usec / 1000))
+}
+
+/// Whether the OOM killer killed anything in the cgroup or below it.
+fn parse_memory_events(memory_events: &str) -> std::io::Result<bool> {
+    keyed_value(memory_events, "oom_kill").map(|count| count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_memory_peak("2097152\n").unwrap(),
+            Memory::new_megabytes(2)
+        );
+        assert_eq!(
+            parse_cpu_stat("usage_usec 1250999\nuser_usec 1200000\nsystem_usec 50999\n").unwrap(),
+            MsTime::new_ms(1250)
+        );
+        assert!(
+            parse_memory_events("low 0\nhigh 0\nmax 3\noom 1\noom_kill 1\noom_group_kill 0\n")
+                .unwrap()
+        );
+        assert!(
+            !parse_memory_events("low 0\nhigh 0\nmax 0\noom 0\noom_kill 0\noom_group_kill 0\n")
+                .unwrap()
+        );
+        assert!(parse_cpu_stat("user_usec 1\n").is_err());
+    }
+}
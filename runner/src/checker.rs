@@ -0,0 +1,321 @@
+use std::path::PathBuf;
+
+use runner_schema::{
+    Language,
+    score::Score,
+    state::{CheckerReport, RunnerState},
+    web::Checker,
+};
+
+use crate::{
+    Result,
+    program::{Execution, Limits, Program, RunContext},
+    runner::Runners,
+    testdata::ResolvedTestCase,
+};
+
+/// What a checker decided about one test case.
+pub enum CheckerVerdict {
+    Accepted(CheckerReport),
+    WrongAnswer(CheckerReport),
+    PartiallyAccepted(Score, CheckerReport),
+    Failed { message: String },
+}
+
+impl CheckerVerdict {
+    // testlib exit codes
+    const EXIT_OK: i32 = 0;
+    const EXIT_WRONG_ANSWER: i32 = 1;
+    const EXIT_PRESENTATION_ERROR: i32 = 2;
+    const EXIT_FAIL: i32 = 3;
+    const EXIT_POINTS: i32 = 7;
+
+    /// Maps a testlib style exit code and stderr message to a verdict.
+    ///
+    /// `name` is how the program is referred to in failure messages.
+    pub fn from_execution(name: &str, execution: &Execution, limits: Limits) -> Self {
+        let usage = execution.usage;
+        if usage.time > limits.wall_time {
+            return CheckerVerdict::Failed {
+                message: format!("{name} timed out after {:?}", usage.time),
+            };
+        }
+
+        if usage.oom_killed || usage.memory > limits.memory {
+            return CheckerVerdict::Failed {
+                message: format!("{name} used {} of memory", usage.memory),
+            };
+        }
+
+        let message = String::from_utf8_lossy(&execution.output.stderr)
+            .trim()
+            .to_string();
+        let report = |message: String| CheckerReport {
+            message,
+            max_memory_usage: usage.memory,
+            ms_time_elapsed: usage.time,
+            ms_cpu_time: usage.cpu_time,
+        };
+
+        match execution.output.status.code() {
+            Some(Self::EXIT_OK) => CheckerVerdict::Accepted(report(message)),
+            Some(Self::EXIT_WRONG_ANSWER | Self::EXIT_PRESENTATION_ERROR) => {
+                CheckerVerdict::WrongAnswer(report(message))
+            }
+            Some(Self::EXIT_POINTS) => {
+                // testlib's `quitp` writes `points <value> <message>`.
+                let value = message
+                    .strip_prefix("points")
+                    .filter(|rest| rest.starts_with(char::is_whitespace))
+                    .unwrap_or(&message)
+                    .trim_start();
+                let (points, rest) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+                match points.parse::<f64>().ok().and_then(Score::new) {
+                    Some(score) => {
+                        CheckerVerdict::PartiallyAccepted(score, report(rest.trim().to_string()))
+                    }
+                    None => CheckerVerdict::Failed {
+                        message: format!("{name} reported invalid points: {message}"),
+                    },
+                }
+            }
+            Some(Self::EXIT_FAIL) => CheckerVerdict::Failed {
+                message: format!("{name} failed: {message}"),
+            },
+            code => CheckerVerdict::Failed {
+                message: format!("{name} exited with {code:?}: {message}"),
+            },
+        }
+    }
+}
+
+/// A compiled checker, run once per test case in its own sandbox.
+pub struct CompiledChecker<'a> {
+    checker: &'a Checker,
+    program: Program<'a>,
+}
+
+impl<'a> CompiledChecker<'a> {
+    const INPUT: &'static str = "input.txt";
+    const OUTPUT: &'static str = "output.txt";
+    const ANSWER: &'static str = "answer.txt";
+
+    /// Compiles the checker in `dir`.
+    ///
+    /// Returns the state to report when it does not compile.
+    pub fn prepare(
+        runners: &'a Runners,
+        checker: &'a Checker,
+        dir: PathBuf,
+        context: RunContext,
+    ) -> Result<std::result::Result<Self, RunnerState>> {
+        let program = match prepare_program(
+            "checker",
+            runners,
+            &checker.lang,
+            &checker.code,
+            dir,
+            context,
+        )? {
+            Ok(program) => program,
+            Err(state) => return Ok(Err(state)),
+        };
+
+        Ok(Ok(Self { checker, program }))
+    }
+
+    /// Judges what the contestant wrote to stdout on `test_case`.
+    pub fn check(&self, test_case: &ResolvedTestCase, output: &[u8]) -> Result<CheckerVerdict> {
+        let dir = self.program.dir();
+        test_case.input.write_to(&dir.join(Self::INPUT))?;
+        std::fs::write(dir.join(Self::OUTPUT), output)?;
+        match &test_case.answer {
+            Some(answer) => answer.write_to(&dir.join(Self::ANSWER))?,
+            None => std::fs::write(dir.join(Self::ANSWER), "")?,
+        }
+
+        let limits = Limits {
+            wall_time: self.checker.ms_time_limit,
+            cpu_time: None,
+            memory: self.checker.memory_limit,
+        };
+        let execution = match self.program.run_with_args(
+            &[Self::INPUT, Self::OUTPUT, Self::ANSWER],
+            std::io::empty(),
+            limits,
+            &|_, _| {},
+        )? {
+            Ok(execution) => execution,
+            Err(_) => {
+                return Ok(CheckerVerdict::Failed {
+                    message: format!("checker wrote more than {}", self.program.output_limit()),
+                });
+            }
+        };
+        log::debug!("Checker Usage: {:?}", execution.usage);
+
+        Ok(CheckerVerdict::from_execution(
+            "checker",
+            &execution,
+            self.program.sandbox_limits(limits),
+        ))
+    }
+}
+
+/// Compiles a problem supplied program, reporting any failure to compile, limits included,
+/// as a [`RunnerState::CheckerError`] since it is the problem's fault rather than the contestant's.
+pub(crate) fn prepare_program<'a>(
+    name: &str,
+    runners: &'a Runners,
+    lang: &'a Language,
+    code: &str,
+    dir: PathBuf,
+    context: RunContext,
+) -> Result<std::result::Result<Program<'a>, RunnerState>> {
+    let lang_runner = runners.get(lang)?;
+    Ok(
+        Program::prepare(lang, lang_runner, code, "", None, dir, context)?
+            .program
+            .map_err(|state| compile_failure(name, state)),
+    )
+}
+
+fn compile_failure(name: &str, state: RunnerState) -> RunnerState {
+    let message = match state {
+        RunnerState::CompileError { stderr } => format!("{name} failed to compile: {stderr}"),
+        RunnerState::CompileTimeout { ms_time_elapsed } => {
+            format!("{name} took longer than {ms_time_elapsed:?} to compile")
+        }
+        RunnerState::CompileMemoryLimit { max_memory_usage } => {
+            format!("{name} used {max_memory_usage} of memory to compile")
+        }
+        state => return state,
+    };
+    RunnerState::CheckerError { message }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::process::ExitStatusExt,
+        process::{ExitStatus, Output},
+    };
+
+    use runner_schema::{memory::Memory, time::MsTime};
+
+    use super::*;
+    use crate::{nsjail::NsJailLog, time::Usage};
+
+    const LIMITS: Limits = Limits {
+        wall_time: MsTime::new_ms(1000),
+        cpu_time: None,
+        memory: Memory::new_megabytes(256),
+    };
+
+    fn execution(status: ExitStatus, stderr: &str) -> Execution {
+        Execution {
+            output: Output {
+                status,
+                stdout: Vec::new(),
+                stderr: stderr.as_bytes().to_vec(),
+            },
+            usage: Usage {
+                memory: Memory::new_megabytes(1),
+                time: MsTime::new_ms(10),
+                cpu_time: MsTime::new_ms(10),
+                oom_killed: false,
+            },
+            jail_log: NsJailLog::default(),
+        }
+    }
+
+    fn verdict(code: i32, stderr: &str) -> CheckerVerdict {
+        // A wait status carries the exit code in its second byte.
+        let execution = execution(ExitStatus::from_raw(code << 8), stderr);
+        CheckerVerdict::from_execution("checker", &execution, LIMITS)
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert!(matches!(
+            verdict(0, "ok \n"),
+            CheckerVerdict::Accepted(report) if report.message == "ok"
+        ));
+        assert!(matches!(
+            verdict(1, "expected 1"),
+            CheckerVerdict::WrongAnswer(report) if report.message == "expected 1"
+        ));
+        assert!(matches!(verdict(2, ""), CheckerVerdict::WrongAnswer(_)));
+        assert!(matches!(
+            verdict(3, "bad answer file"),
+            CheckerVerdict::Failed { message } if message == "checker failed: bad answer file"
+        ));
+        for code in [4, 5, 8, 255] {
+            assert!(matches!(verdict(code, ""), CheckerVerdict::Failed { .. }));
+        }
+        let killed = execution(ExitStatus::from_raw(libc::SIGKILL), "");
+        assert!(matches!(
+            CheckerVerdict::from_execution("checker", &killed, LIMITS),
+            CheckerVerdict::Failed { .. }
+        ));
+    }
+
+    #[test]
+    fn points() {
+        assert!(matches!(
+            verdict(7, "0.5 half of the answers\n"),
+            CheckerVerdict::PartiallyAccepted(score, report)
+                if score.as_f64() == 0.5 && report.message == "half of the answers"
+        ));
+        assert!(matches!(
+            verdict(7, "points 0.25 1 of 4 queries answered\n"),
+            CheckerVerdict::PartiallyAccepted(score, report)
+                if score.as_f64() == 0.25 && report.message == "1 of 4 queries answered"
+        ));
+        assert!(matches!(
+            verdict(7, "3"),
+            CheckerVerdict::PartiallyAccepted(score, report)
+                if score.as_f64() == 3.0 && report.message.is_empty()
+        ));
+        for malformed in [
+            "",
+            "points",
+            "points half",
+            "pointsx 1",
+            "half",
+            "-1 negative",
+            "NaN",
+            "inf",
+            "1e400",
+        ] {
+            assert!(
+                matches!(verdict(7, malformed), CheckerVerdict::Failed { .. }),
+                "{malformed}"
+            );
+        }
+    }
+
+    #[test]
+    fn compile_failures_are_checker_errors() {
+        for state in [
+            RunnerState::CompileError {
+                stderr: "error".to_string(),
+            },
+            RunnerState::CompileTimeout {
+                ms_time_elapsed: MsTime::new_ms(10_000),
+            },
+            RunnerState::CompileMemoryLimit {
+                max_memory_usage: Memory::new_megabytes(2048),
+            },
+        ] {
+            assert!(matches!(
+                compile_failure("checker", state),
+                RunnerState::CheckerError { message } if message.starts_with("checker ")
+            ));
+        }
+        assert_eq!(
+            compile_failure("checker", RunnerState::Cancelled),
+            RunnerState::Cancelled
+        );
+    }
+}
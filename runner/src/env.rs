@@ -1,11 +1,54 @@
+use std::{num::NonZeroUsize, path::PathBuf};
+
 use runner_schema::{memory::Memory, time::MsTime};
 
+use crate::time::Measurement;
+
 #[derive(Debug, envman::EnvMan)]
 pub struct RunnerOption {
     #[envman(parser = compile_time_limit_seconds)]
     pub compile_time_limit_seconds: MsTime,
     #[envman(parser = compile_memory_limit_megabytes)]
     pub compile_memory_limit_megabytes: Memory,
+    /// `cgroup` or `gtime`. Replaced by [`Measurement::setup`]'s fallback on startup.
+    #[envman(default)]
+    pub measurement: Measurement,
+    /// How many submissions run at once, each on a CPU of its own when there are enough.
+    /// At least one, or nothing would ever run.
+    #[envman(parser = workers, default = default_workers())]
+    pub workers: usize,
+    /// How many submissions may wait for a worker before new ones are turned away.
+    #[envman(default = 256)]
+    pub queue_capacity: usize,
+    /// Leaves the directories of runs that did not succeed under [`RUNNING_PATH`] to debug them.
+    #[envman(default)]
+    pub keep_failed_runs: bool,
+    /// New work is refused when less than this is free on the volume of [`RUNNING_PATH`].
+    #[envman(parser = min_free_disk_megabytes, default = Memory::new_megabytes(1024))]
+    pub min_free_disk_megabytes: Memory,
+    /// How many files a multi-file submission may have.
+    #[envman(default = 256)]
+    pub max_submission_files: usize,
+    /// How large the files of a multi-file submission may be in total, once extracted.
+    #[envman(parser = max_submission_megabytes, default = Memory::new_megabytes(16))]
+    pub max_submission_megabytes: Memory,
+    /// The language registry loaded by [`crate::runner::Runners::new`].
+    #[envman(default = PathBuf::from(LANGUAGES_PATH))]
+    pub languages_path: PathBuf,
+    /// Where test data uploaded with `PUT /testdata/{sha256}` is kept, see [`crate::testdata`].
+    #[envman(default = PathBuf::from(TESTDATA_PATH))]
+    pub testdata_path: PathBuf,
+    /// How large a single upload of test data may be.
+    #[envman(parser = max_testdata_megabytes, default = Memory::new_megabytes(1024))]
+    pub max_testdata_megabytes: Memory,
+}
+
+fn default_workers() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+fn workers(value: &str) -> Result<usize, <NonZeroUsize as std::str::FromStr>::Err> {
+    value.parse::<NonZeroUsize>().map(usize::from)
 }
 
 fn compile_time_limit_seconds(value: &str) -> Result<MsTime, <u64 as std::str::FromStr>::Err> {
@@ -16,6 +59,18 @@ fn compile_memory_limit_megabytes(value: &str) -> Result<Memory, <u64 as std::st
     value.parse::<u64>().map(Memory::new_megabytes)
 }
 
+fn max_submission_megabytes(value: &str) -> Result<Memory, <u64 as std::str::FromStr>::Err> {
+    value.parse::<u64>().map(Memory::new_megabytes)
+}
+
+fn max_testdata_megabytes(value: &str) -> Result<Memory, <u64 as std::str::FromStr>::Err> {
+    value.parse::<u64>().map(Memory::new_megabytes)
+}
+
+fn min_free_disk_megabytes(value: &str) -> Result<Memory, <u64 as std::str::FromStr>::Err> {
+    value.parse::<u64>().map(Memory::new_megabytes)
+}
+
 pub const RUNNER_PATH: &str = env!("RUNNER_PATH");
 pub const RUNNING_PATH: &str = env!("RUNNING_PATH");
 pub const NIX_STORE_PATH: &str = env!("NIX_STORE_PATH");
@@ -23,6 +78,9 @@ pub const NIX_BIN: &str = env!("NIX_BIN");
 pub const SH_CMD: &str = concat!(env!("NIX_BIN"), "/sh");
 pub const NSJAIL_CMD: &str = concat!(env!("NIX_BIN"), "/nsjail");
 pub const TIME_CMD: &str = concat!(env!("NIX_BIN"), "/time");
+pub const LANGUAGES_PATH: &str = env!("LANGUAGES_PATH");
+pub const TESTDATA_PATH: &str = env!("TESTDATA_PATH");
+pub const CGROUP_PATH: &str = "/sys/fs/cgroup/runner";
 
 pub const PERMISSION_ID_STR: &str = env!("PERMISSION_ID_STR");
 pub const PERMISSION_ID: u32 = match u32::from_str_radix(PERMISSION_ID_STR, 10) {
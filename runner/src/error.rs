@@ -1,7 +1,73 @@
+use runner_schema::{Language, memory::Memory};
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("failed io operation: {0}")]
     IO(#[from] std::io::Error),
+    #[error("request has no test cases")]
+    NoTestCases,
+    #[error("unknown language: {0}")]
+    UnknownLanguage(Language),
+    #[error("invalid submission files: {0}")]
+    InvalidFiles(String),
+    #[error("invalid test case data: {0}")]
+    InvalidData(String),
+    #[error("invalid io mode: {0}")]
+    InvalidIoMode(String),
+    #[error("not a lowercase hex sha256: {0}")]
+    InvalidHash(String),
+    #[error("no test data is stored as {0}")]
+    UnknownTestData(String),
+    #[error("test data was uploaded as {expected} but hashes to {actual}")]
+    HashMismatch { expected: String, actual: String },
+    #[error("test data is larger than {0}")]
+    TestDataTooLarge(Memory),
+    #[error("{lang} has no compile option {option}")]
+    UnknownCompileOption { lang: Language, option: String },
+    #[error("only {available} of disk is free, at least {required} is needed")]
+    InsufficientDisk { available: Memory, required: Memory },
+    #[error("job queue is full")]
+    QueueFull,
+    #[error("job queue is closed")]
+    QueueClosed,
+}
+
+impl Error {
+    /// Whether the request is at fault rather than the runner, so that it is not worth retrying.
+    pub fn is_client_error(&self) -> bool {
+        matches!(
+            self,
+            Error::NoTestCases
+                | Error::UnknownLanguage(_)
+                | Error::InvalidFiles(_)
+                | Error::InvalidData(_)
+                | Error::InvalidIoMode(_)
+                | Error::InvalidHash(_)
+                | Error::UnknownTestData(_)
+                | Error::HashMismatch { .. }
+                | Error::TestDataTooLarge(_)
+                | Error::UnknownCompileOption { .. }
+        )
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_errors() {
+        assert!(Error::NoTestCases.is_client_error());
+        assert!(
+            Error::HashMismatch {
+                expected: "a".to_string(),
+                actual: "b".to_string(),
+            }
+            .is_client_error()
+        );
+        assert!(!Error::QueueFull.is_client_error());
+        assert!(!Error::IO(std::io::Error::other("disk")).is_client_error());
+    }
+}
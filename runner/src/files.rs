@@ -0,0 +1,430 @@
+use std::{
+    collections::HashSet,
+    io::{Cursor, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use base64::Engine;
+use runner_schema::web::{IoMode, RunnerRequest, SubmissionFiles};
+
+use crate::{
+    Error, Result,
+    env::{PERMISSION_ID, RunnerOption},
+    nsjail::{NsJailBuilder, NsJailLog},
+    runner::LangRunner,
+    time::GTime,
+};
+
+/// A file of a multi-file submission, at a path checked to stay inside its directory.
+pub struct SubmissionFile {
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+/// The files of `request` besides its code, checked against the limits of `option`
+/// and against the entry point of `lang_runner`.
+///
+/// Fails with [`Error::InvalidFiles`] for anything the submitter got wrong.
+pub fn prepare(
+    request: &RunnerRequest,
+    lang_runner: &LangRunner,
+    option: &RunnerOption,
+) -> Result<Vec<SubmissionFile>> {
+    let Some(files) = &request.files else {
+        return Ok(Vec::new());
+    };
+    let files = extract(files, option)?;
+
+    let entry_point = PathBuf::from(lang_runner.entry_point(&request.code));
+    if let Some(file) = files.iter().find(|file| {
+        request
+            .problem_files
+            .keys()
+            .any(|path| file.path == Path::new(path))
+    }) {
+        return Err(Error::InvalidFiles(format!(
+            "{} is a file of the problem",
+            file.path.display()
+        )));
+    }
+    let has_entry_point = files.iter().any(|file| file.path == entry_point);
+    match (request.code.is_empty(), has_entry_point) {
+        (true, false) => Err(Error::InvalidFiles(format!(
+            "no code is given and {} is missing",
+            entry_point.display()
+        ))),
+        (false, true) => Err(Error::InvalidFiles(format!(
+            "{} is both given as code and among the files",
+            entry_point.display()
+        ))),
+        _ => Ok(files),
+    }
+}
+
+/// Files of the problem a submission is run for, kept outside of its directory
+/// and mounted read-only into it.
+#[derive(Clone)]
+pub struct ProblemFiles {
+    dir: PathBuf,
+    paths: Vec<PathBuf>,
+}
+
+impl ProblemFiles {
+    /// The problem files of `request`, checked against the limits of `option`
+    /// and against the entry point of `lang_runner`.
+    pub fn check(
+        request: &RunnerRequest,
+        lang_runner: &LangRunner,
+        option: &RunnerOption,
+    ) -> Result<Vec<SubmissionFile>> {
+        let mut budget = Budget::new(option);
+        let files = request
+            .problem_files
+            .iter()
+            .map(|(path, contents)| budget.take(Path::new(path), contents.as_bytes()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let entry_point = PathBuf::from(lang_runner.entry_point(&request.code));
+        if files.iter().any(|file| file.path == entry_point) {
+            return Err(Error::InvalidFiles(format!(
+                "{} is the entry point of the submission",
+                entry_point.display()
+            )));
+        }
+        Ok(files)
+    }
+
+    /// Writes the problem `files` checked by [`ProblemFiles::check`] under `dir`,
+    /// [`None`] if there are none.
+    pub fn write(files: Vec<SubmissionFile>, dir: PathBuf) -> Result<Option<Self>> {
+        if files.is_empty() {
+            return Ok(None);
+        }
+        write(&dir, &files)?;
+        Ok(Some(Self {
+            dir,
+            paths: files.into_iter().map(|file| file.path).collect(),
+        }))
+    }
+
+    /// Mounts every file read-only at its path inside the sandbox root.
+    pub fn mount(&self, builder: &mut NsJailBuilder) {
+        for path in &self.paths {
+            builder.mount_ro_dest(
+                &self.dir.join(path).to_string_lossy(),
+                &Path::new("/").join(path).to_string_lossy(),
+            );
+        }
+    }
+}
+
+/// Fails with [`Error::InvalidIoMode`] unless the files of an [`IoMode::File`] are two
+/// distinct plain file names that nothing else in the working directory is written to:
+/// no file of the submission, `files` included, or of the problem, nothing compiling
+/// writes and none of the reports of a run.
+pub fn check_io_mode(
+    request: &RunnerRequest,
+    lang_runner: &LangRunner,
+    files: &[SubmissionFile],
+) -> Result<()> {
+    let IoMode::File { input, output } = &request.io_mode else {
+        return Ok(());
+    };
+    if request.interactor.is_some() {
+        return Err(Error::InvalidIoMode(
+            "file I/O cannot be used with an interactor".to_string(),
+        ));
+    }
+    if input == output {
+        return Err(Error::InvalidIoMode(format!(
+            "{input} is both the input and the output file"
+        )));
+    }
+
+    let entry_point = lang_runner.entry_point(&request.code);
+    let taken = |name: &str| {
+        let top_level =
+            |path: &Path| path.components().next() == Some(Component::Normal(name.as_ref()));
+        name == entry_point
+            || files.iter().any(|file| top_level(&file.path))
+            || request
+                .problem_files
+                .keys()
+                .any(|path| top_level(Path::new(path)))
+    };
+    for name in [input, output] {
+        let mut components = Path::new(name).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            return Err(Error::InvalidIoMode(format!(
+                "{name} is not a file name in the working directory"
+            )));
+        }
+        if taken(name) {
+            return Err(Error::InvalidIoMode(format!(
+                "{name} is a file of the submission or of the problem"
+            )));
+        }
+        if lang_runner.option().is_compile_output(name) {
+            return Err(Error::InvalidIoMode(format!(
+                "{name} is written by compiling the submission"
+            )));
+        }
+        if [NsJailLog::LOG, GTime::TIME_TXT].contains(&name.as_str()) {
+            return Err(Error::InvalidIoMode(format!(
+                "{name} is reserved for the runner"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Writes `files` under `dir`, creating the directories they are in for the sandbox user.
+pub fn write(dir: &Path, files: &[SubmissionFile]) -> Result<()> {
+    for file in files {
+        let mut parent = dir.to_path_buf();
+        if let Some(dirs) = file.path.parent() {
+            for component in dirs.components() {
+                parent.push(component);
+                if !parent.exists() {
+                    std::fs::create_dir(&parent)?;
+                    std::os::unix::fs::chown(&parent, Some(PERMISSION_ID), Some(PERMISSION_ID))?;
+                }
+            }
+        }
+        std::fs::write(dir.join(&file.path), &file.contents)?;
+    }
+    Ok(())
+}
+
+fn extract(files: &SubmissionFiles, option: &RunnerOption) -> Result<Vec<SubmissionFile>> {
+    let mut budget = Budget::new(option);
+    match files {
+        SubmissionFiles::Map(map) => map
+            .iter()
+            .map(|(path, contents)| budget.take(Path::new(path), contents.as_bytes()))
+            .collect(),
+        SubmissionFiles::Tar(archive) => {
+            let mut archive = tar::Archive::new(Cursor::new(decode(archive)?));
+            let mut files = Vec::new();
+            for entry in archive.entries().map_err(invalid_archive)? {
+                let entry = entry.map_err(invalid_archive)?;
+                let path = entry.path().map_err(invalid_archive)?.into_owned();
+                match entry.header().entry_type() {
+                    tar::EntryType::Directory => continue,
+                    tar::EntryType::Regular => {}
+                    _ => return Err(not_a_file(&path)),
+                }
+                let contents = budget.read(&path, entry)?;
+                files.push(budget.take(&path, &contents)?);
+            }
+            Ok(files)
+        }
+        SubmissionFiles::Zip(archive) => {
+            let mut archive =
+                zip::ZipArchive::new(Cursor::new(decode(archive)?)).map_err(invalid_archive)?;
+            let mut files = Vec::new();
+            for index in 0..archive.len() {
+                let entry = archive.by_index(index).map_err(invalid_archive)?;
+                let path = PathBuf::from(entry.name());
+                if entry.is_dir() {
+                    continue;
+                }
+                if !entry.is_file() {
+                    return Err(not_a_file(&path));
+                }
+                let contents = budget.read(&path, entry)?;
+                files.push(budget.take(&path, &contents)?);
+            }
+            Ok(files)
+        }
+    }
+}
+
+/// How many more files, and bytes in total, a submission may have.
+struct Budget {
+    files: usize,
+    bytes: u64,
+    seen: HashSet<PathBuf>,
+}
+
+impl Budget {
+    fn new(option: &RunnerOption) -> Self {
+        Self {
+            files: option.max_submission_files,
+            bytes: option.max_submission_megabytes.as_bytes(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Reads an archive entry, giving up as soon as it is over the budget
+    /// rather than trusting the size it claims.
+    fn read(&self, path: &Path, entry: impl Read) -> Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        entry
+            .take(self.bytes.saturating_add(1))
+            .read_to_end(&mut contents)
+            .map_err(invalid_archive)?;
+        if contents.len() as u64 > self.bytes {
+            return Err(self.too_large(path));
+        }
+        Ok(contents)
+    }
+
+    fn take(&mut self, path: &Path, contents: &[u8]) -> Result<SubmissionFile> {
+        let path = relative_path(path)?;
+        if !self.seen.insert(path.clone()) {
+            return Err(Error::InvalidFiles(format!(
+                "{} is given more than once",
+                path.display()
+            )));
+        }
+
+        self.files = self
+            .files
+            .checked_sub(1)
+            .ok_or_else(|| Error::InvalidFiles("too many files are given".to_string()))?;
+        self.bytes = self
+            .bytes
+            .checked_sub(contents.len() as u64)
+            .ok_or_else(|| self.too_large(&path))?;
+
+        Ok(SubmissionFile {
+            path,
+            contents: contents.to_vec(),
+        })
+    }
+
+    fn too_large(&self, path: &Path) -> Error {
+        Error::InvalidFiles(format!("the files are too large as of {}", path.display()))
+    }
+}
+
+/// `path` if it is relative and stays inside the directory it is relative to.
+fn relative_path(path: &Path) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(Error::InvalidFiles(format!(
+                    "{} is not a relative path inside the submission",
+                    path.display()
+                )));
+            }
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Err(Error::InvalidFiles("a file has an empty path".to_string()));
+    }
+    Ok(relative)
+}
+
+fn decode(archive: &str) -> Result<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD
+        .decode(archive)
+        .map_err(|err| Error::InvalidFiles(format!("archive is not valid base64: {err}")))
+}
+
+fn invalid_archive(err: impl std::fmt::Display) -> Error {
+    Error::InvalidFiles(format!("invalid archive: {err}"))
+}
+
+fn not_a_file(path: &Path) -> Error {
+    Error::InvalidFiles(format!(
+        "{} is neither a regular file nor a directory",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::LangRunnerOption;
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            relative_path(Path::new("./src/lib.rs")).unwrap(),
+            PathBuf::from("src/lib.rs")
+        );
+        assert!(relative_path(Path::new("../etc/passwd")).is_err());
+        assert!(relative_path(Path::new("src/../../x")).is_err());
+        assert!(relative_path(Path::new("/etc/passwd")).is_err());
+        assert!(relative_path(Path::new("./")).is_err());
+    }
+
+    #[test]
+    fn budget() {
+        let mut budget = Budget {
+            files: 2,
+            bytes: 10,
+            seen: HashSet::new(),
+        };
+        assert!(budget.take(Path::new("a.rs"), b"12345").is_ok());
+        assert!(budget.take(Path::new("./a.rs"), b"").is_err());
+        assert!(budget.take(Path::new("b.rs"), b"123456").is_err());
+
+        let mut budget = Budget {
+            files: 1,
+            bytes: 10,
+            seen: HashSet::new(),
+        };
+        assert!(budget.take(Path::new("a.rs"), b"").is_ok());
+        assert!(budget.take(Path::new("b.rs"), b"").is_err());
+    }
+
+    #[test]
+    fn io_mode() {
+        let lang_runner = LangRunner::WithCompile {
+            file_name: "main.py".to_string(),
+            compile_cmd: "python -m py_compile main.py".to_string(),
+            run_cmd: "python main.py".to_string(),
+            option: LangRunnerOption {
+                compile_outputs: vec!["__pycache__".to_string(), "*.pyc".to_string()],
+                ..Default::default()
+            },
+        };
+        let request = |input: &str, output: &str| -> RunnerRequest {
+            serde_json::from_value(serde_json::json!({
+                "lang": "python313",
+                "code": "",
+                "ms_time_limit": 1000,
+                "ms_wall_time_limit": null,
+                "memory_limit": "256M",
+                "test_cases": [],
+                "checker": null,
+                "interactor": null,
+                "problem_files": { "lib/grader.py": "" },
+                "io_mode": { "File": { "input": input, "output": output } },
+            }))
+            .unwrap()
+        };
+
+        let files = [SubmissionFile {
+            path: PathBuf::from("src/util.py"),
+            contents: Vec::new(),
+        }];
+        let check = |input, output| check_io_mode(&request(input, output), &lang_runner, &files);
+
+        assert!(check("input.txt", "output.txt").is_ok());
+        assert!(check("data.txt", "data.txt").is_err());
+        assert!(check("../input.txt", "output.txt").is_err());
+        assert!(check("in/input.txt", "output.txt").is_err());
+        for taken in [
+            "main.py",
+            "src",
+            "lib",
+            "__pycache__",
+            "main.pyc",
+            "nsjail.log",
+            "time.txt",
+        ] {
+            assert!(check("input.txt", taken).is_err(), "{taken}");
+            assert!(check(taken, "output.txt").is_err(), "{taken}");
+        }
+    }
+}
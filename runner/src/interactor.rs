@@ -0,0 +1,314 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    path::PathBuf,
+    process::Output,
+    sync::{Mutex, PoisonError},
+};
+
+use runner_schema::{
+    state::{Party, RunnerState, Transcript, TranscriptEntry},
+    web::Interactor,
+};
+
+use crate::{
+    Error, Result,
+    checker::{CheckerVerdict, prepare_program},
+    program::{Execution, Limits, Program, RunContext, join, read_capped, take_pipe},
+    runner::Runners,
+    testdata::ResolvedTestCase,
+};
+
+/// A compiled interactor, run once per test case alongside the contestant.
+pub struct CompiledInteractor<'a> {
+    interactor: &'a Interactor,
+    program: Program<'a>,
+}
+
+pub struct Interaction {
+    pub contestant: Execution,
+    pub verdict: CheckerVerdict,
+    pub transcript: Option<Transcript>,
+}
+
+impl<'a> CompiledInteractor<'a> {
+    const INPUT: &'static str = "input.txt";
+    const ANSWER: &'static str = "answer.txt";
+
+    /// Compiles the interactor in `dir`.
+    ///
+    /// Returns the state to report when it does not compile.
+    pub fn prepare(
+        runners: &'a Runners,
+        interactor: &'a Interactor,
+        dir: PathBuf,
+        context: RunContext,
+    ) -> Result<std::result::Result<Self, RunnerState>> {
+        let program = match prepare_program(
+            "interactor",
+            runners,
+            &interactor.lang,
+            &interactor.code,
+            dir,
+            context,
+        )? {
+            Ok(program) => program,
+            Err(state) => return Ok(Err(state)),
+        };
+
+        Ok(Ok(Self {
+            interactor,
+            program,
+        }))
+    }
+
+    /// Runs `contestant` with its stdin and stdout wired to the interactor.
+    pub fn interact(
+        &self,
+        contestant: &Program,
+        test_case: &ResolvedTestCase,
+        limits: Limits,
+    ) -> Result<Interaction> {
+        let dir = self.program.dir();
+        test_case.input.write_to(&dir.join(Self::INPUT))?;
+        match &test_case.answer {
+            Some(answer) => answer.write_to(&dir.join(Self::ANSWER))?,
+            None => std::fs::write(dir.join(Self::ANSWER), "")?,
+        }
+
+        let interactor_limits = Limits {
+            wall_time: self.interactor.ms_time_limit,
+            cpu_time: None,
+            memory: self.interactor.memory_limit,
+        };
+        let mut contestant_child = contestant.spawn(&[], limits)?;
+        let mut interactor_child = match self
+            .program
+            .spawn(&[Self::INPUT, Self::ANSWER], interactor_limits)
+        {
+            Ok(child) => child,
+            Err(err) => {
+                let _ = contestant_child.child.kill();
+                let _ = contestant_child.wait();
+                return Err(err);
+            }
+        };
+
+        let recorder = self
+            .interactor
+            .transcript
+            .then(|| Mutex::new(Recorder::default()));
+
+        let contestant_stdin = take_pipe(contestant_child.child.stdin.take())?;
+        let contestant_stdout = take_pipe(contestant_child.child.stdout.take())?;
+        let contestant_stderr = take_pipe(contestant_child.child.stderr.take())?;
+        let interactor_stdin = take_pipe(interactor_child.child.stdin.take())?;
+        let interactor_stdout = take_pipe(interactor_child.child.stdout.take())?;
+        let interactor_stderr = take_pipe(interactor_child.child.stderr.take())?;
+
+        let ((contestant_output, contestant_elapsed), (interactor_output, interactor_elapsed)) =
+            std::thread::scope(|scope| {
+                let recorder = recorder.as_ref();
+                scope.spawn(move || {
+                    proxy(
+                        contestant_stdout,
+                        interactor_stdin,
+                        Party::Contestant,
+                        recorder,
+                    )
+                });
+                scope.spawn(move || {
+                    proxy(
+                        interactor_stdout,
+                        contestant_stdin,
+                        Party::Interactor,
+                        recorder,
+                    )
+                });
+                // Only the runner's memory is at stake here, so stderr is cut off without a kill.
+                let contestant_stderr = scope.spawn(|| {
+                    read_capped(contestant_stderr, contestant.output_limit(), None, |_| {})
+                });
+                let interactor_stderr = scope.spawn(|| {
+                    read_capped(interactor_stderr, self.program.output_limit(), None, |_| {})
+                });
+
+                let (contestant_status, contestant_elapsed) = contestant_child.wait()?;
+                let (interactor_status, interactor_elapsed) = interactor_child.wait()?;
+
+                Ok::<_, Error>((
+                    (
+                        Output {
+                            status: contestant_status,
+                            stdout: Vec::new(),
+                            stderr: join(contestant_stderr)?.bytes,
+                        },
+                        contestant_elapsed,
+                    ),
+                    (
+                        Output {
+                            status: interactor_status,
+                            stdout: Vec::new(),
+                            stderr: join(interactor_stderr)?.bytes,
+                        },
+                        interactor_elapsed,
+                    ),
+                ))
+            })?;
+
+        let contestant = Execution {
+            output: contestant_output,
+            usage: contestant.measure(contestant_elapsed)?,
+            jail_log: contestant.jail_log()?,
+        };
+
+        let interactor = Execution {
+            output: interactor_output,
+            usage: self.program.measure(interactor_elapsed)?,
+            jail_log: self.program.jail_log()?,
+        };
+
+        let verdict = CheckerVerdict::from_execution(
+            "interactor",
+            &interactor,
+            self.program.sandbox_limits(interactor_limits),
+        );
+
+        Ok(Interaction {
+            contestant,
+            verdict,
+            transcript: recorder.map(|recorder| {
+                recorder
+                    .into_inner()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .finish()
+            }),
+        })
+    }
+}
+
+/// Copies `from` into `to` until `from` closes, keeping `from` drained
+/// after `to` has gone away so its writer never blocks on a full pipe.
+fn proxy(mut from: impl Read, to: impl Write, party: Party, recorder: Option<&Mutex<Recorder>>) {
+    let mut to = Some(to);
+    let mut buf = [0; 8192];
+    loop {
+        let len = match from.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+
+        if let Some(recorder) = recorder {
+            recorder
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record(party, &buf[..len]);
+        }
+
+        if let Some(writer) = &mut to
+            && writer
+                .write_all(&buf[..len])
+                .and_then(|()| writer.flush())
+                .is_err()
+        {
+            to = None;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    entries: Vec<(Party, Vec<u8>)>,
+    len: usize,
+    truncated: bool,
+}
+
+impl Recorder {
+    const MAX_LEN: usize = 1024 * 1024;
+
+    fn record(&mut self, from: Party, data: &[u8]) {
+        if self.truncated {
+            return;
+        }
+        if self.len + data.len() > Self::MAX_LEN {
+            self.truncated = true;
+            return;
+        }
+        self.len += data.len();
+
+        match self.entries.last_mut() {
+            Some((party, buf)) if *party == from => buf.extend_from_slice(data),
+            _ => self.entries.push((from, data.to_vec())),
+        }
+    }
+
+    fn finish(self) -> Transcript {
+        Transcript {
+            entries: self
+                .entries
+                .into_iter()
+                .map(|(from, data)| TranscriptEntry {
+                    from,
+                    data: String::from_utf8_lossy(&data).to_string(),
+                })
+                .collect(),
+            truncated: self.truncated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(from: Party, data: &str) -> TranscriptEntry {
+        TranscriptEntry {
+            from,
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn interleaving() {
+        let mut recorder = Recorder::default();
+        recorder.record(Party::Interactor, b"3\n");
+        recorder.record(Party::Contestant, b"? 1");
+        recorder.record(Party::Contestant, b"\n");
+        recorder.record(Party::Interactor, b"<\n");
+        recorder.record(Party::Contestant, b"! 2\n");
+
+        assert_eq!(
+            recorder.finish(),
+            Transcript {
+                entries: vec![
+                    entry(Party::Interactor, "3\n"),
+                    entry(Party::Contestant, "? 1\n"),
+                    entry(Party::Interactor, "<\n"),
+                    entry(Party::Contestant, "! 2\n"),
+                ],
+                truncated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn truncation() {
+        let mut recorder = Recorder::default();
+        recorder.record(Party::Interactor, &vec![b'a'; Recorder::MAX_LEN - 1]);
+        recorder.record(Party::Contestant, b"b");
+        let transcript = recorder.finish();
+        assert!(!transcript.truncated);
+        assert_eq!(transcript.entries.len(), 2);
+
+        let mut recorder = Recorder::default();
+        recorder.record(Party::Interactor, &vec![b'a'; Recorder::MAX_LEN - 1]);
+        recorder.record(Party::Contestant, b"bc");
+        // Nor is anything after it, so the transcript stays a prefix of the exchange.
+        recorder.record(Party::Interactor, b"d");
+        let transcript = recorder.finish();
+        assert!(transcript.truncated);
+        assert_eq!(transcript.entries.len(), 1);
+        assert_eq!(transcript.entries[0].data.len(), Recorder::MAX_LEN - 1);
+    }
+}
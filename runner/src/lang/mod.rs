@@ -1,4 +1,3 @@
-use more_convert::VariantName;
 use runner_schema::Language;
 
 use crate::env::RUNNER_PATH;
@@ -12,6 +11,6 @@ pub trait LangExt {
 
 impl LangExt for Language {
     fn runner_path(&self) -> String {
-        format!("{}/{}", RUNNER_PATH, self.variant_name())
+        format!("{}/{}", RUNNER_PATH, self.as_str())
     }
 }
@@ -1,24 +1,42 @@
-use env::{PERMISSION_ID, RUNNING_PATH, RunnerOption, SH_CMD};
-use lang::LangExt;
-use nsjail::NsJailBuilder;
-use runner::{RunCommand, Runners};
+use cancel::Cancellation;
+use checker::{CheckerVerdict, CompiledChecker};
+use env::{PERMISSION_ID, RUNNING_PATH, RunnerOption};
+use files::{ProblemFiles, SubmissionFile};
+use interactor::CompiledInteractor;
+use nsjail::NsJailLog;
+use program::{Execution, Limits, Pipe, Program, RunContext, read_capped};
+use runner::Runners;
+use signal::Termination;
 use std::{
-    io::Write,
+    fs::File,
+    io::Read,
     path::{Path, PathBuf},
-    process::Stdio,
+    process::Output,
 };
-use time::GTime;
+use testdata::{ResolvedTestCase, TestDataStore};
+use time::Usage;
 
 use runner_schema::{
-    memory::Memory,
-    state::RunnerState,
-    web::{RunnerRequest, RunnerResponse},
+    data::CapturedOutput,
+    state::{CompileReport, RunnerState, Transcript},
+    web::{IoMode, RunEvent, RunnerRequest, RunnerResponse},
 };
 
+pub mod affinity;
+pub mod cancel;
+pub mod cgroup;
+pub mod checker;
+pub mod files;
+pub mod interactor;
 pub mod lang;
 pub mod nsjail;
+pub mod program;
+pub mod queue;
 pub mod runner;
+pub mod signal;
+pub mod testdata;
 pub mod time;
+pub mod workdir;
 
 pub mod env;
 
@@ -30,150 +48,483 @@ pub fn run(
     request: RunnerRequest,
     option: &RunnerOption,
 ) -> Result<RunnerResponse> {
-    let uid = ulid::Ulid::new();
-    log::debug!("Started runner {}: {:#?}", uid, request);
+    let cancellation = Cancellation::default();
+    run_submission(
+        ulid::Ulid::new(),
+        runners,
+        check_request(runners, request, option)?,
+        option,
+        None,
+        &cancellation,
+        &|_| {},
+    )
+}
+
+/// Runs `request` in a directory named after `uid` with every sandbox pinned to `cpu`,
+/// passing what happens to `on_event` as it happens.
+///
+/// Reports [`RunnerState::Cancelled`] once `cancellation` is cancelled.
+/// [`RunEvent::Finished`] is left to the caller, which also has to report errors.
+pub fn run_submission(
+    uid: ulid::Ulid,
+    runners: &Runners,
+    checked: CheckedRequest,
+    option: &RunnerOption,
+    cpu: Option<usize>,
+    cancellation: &Cancellation,
+    on_event: &(dyn Fn(RunEvent) + Sync),
+) -> Result<RunnerResponse> {
+    log::debug!("Started runner {}: {:#?}", uid, checked.request);
+
+    if cancellation.is_cancelled() {
+        return Ok(failed_before_run(RunnerState::Cancelled, None));
+    }
+
+    workdir::check_free_space(option)?;
+
+    let context = RunContext {
+        option,
+        output_limit: checked.request.output_limit,
+        cpu,
+        cancellation,
+    };
+
+    let root_dir = create_dir_by_uid(uid)?;
+    let result = match run_in_dir(&root_dir, runners, checked, context, on_event) {
+        // Killing a sandbox can leave nothing to measure behind.
+        Err(err) if cancellation.is_cancelled() => {
+            log::debug!("Cancelled run {} failed: {}", uid, err);
+            Ok(failed_before_run(RunnerState::Cancelled, None))
+        }
+        result => result,
+    };
+
+    // A cancelled run has nothing worth looking into.
+    let failed = !matches!(
+        &result,
+        Ok(response) if response.state.is_success() || response.state == RunnerState::Cancelled
+    );
+    if option.keep_failed_runs && failed {
+        log::info!("Keeping failed run in {}", root_dir.display());
+    } else if let Err(err) = workdir::remove(&root_dir) {
+        log::warn!("Failed to remove {}: {}", root_dir.display(), err);
+    }
 
-    let lang_runner = runners.get(&request.lang);
-    let uid = ulid::Ulid::new();
+    result
+}
 
-    let current_dir = create_dir_by_uid(uid)?;
+fn run_in_dir(
+    root_dir: &Path,
+    runners: &Runners,
+    checked: CheckedRequest,
+    context: RunContext,
+    on_event: &(dyn Fn(RunEvent) + Sync),
+) -> Result<RunnerResponse> {
+    let CheckedRequest {
+        request,
+        files,
+        problem_files,
+        test_cases: resolved,
+    } = checked;
+    let lang_runner = runners.get(&request.lang)?;
+    let compile_flags = lang_runner.compile_flags(&request.lang, &request.compile_options)?;
+    let cancellation = context.cancellation;
+
+    let current_dir = create_sub_dir(root_dir, "submission")?;
+    files::write(&current_dir, &files)?;
+    // Kept out of the submission directory, which the compile sandbox can write to.
+    let problem_files = ProblemFiles::write(problem_files, create_sub_dir(root_dir, "problem")?)?;
 
     log::debug!("Starting runner in directory: {}", current_dir.display());
 
-    if let Some(file_name) = lang_runner.file_name() {
-        let path = current_dir.join(file_name);
-        log::debug!("Writing to File: {}", path.display());
-        std::fs::write(path, request.code.clone())?;
-    }
-
-    let lang_runner_path = request.lang.runner_path();
-    let bin_path = request.lang.bin_path();
-
-    if let Some(compile_cmd) = lang_runner.compile_cmd() {
-        log::debug!("Compile command: {}", compile_cmd);
-
-        let mut builder = NsJailBuilder::new_with(GTime::new_cmd());
-        builder
-            .time_limit(option.compile_time_limit_seconds)
-            .memory_limit(option.compile_memory_limit_megabytes)
-            .proc_writable(true)
-            .arg("--rlimit_fsize")
-            .arg("100")
-            .arg("--rlimit_nofile")
-            .arg("128")
-            .cwd(&current_dir)
-            .env("PATH", &bin_path)
-            .mount_ro(&lang_runner_path)
-            .tmpfsmount("/tmp", Memory::new_megabytes(512))
-            .writable();
-
-        if let Some(f) = lang_runner.option().more_compile {
-            f(&mut builder)
+    on_event(RunEvent::Compiling);
+
+    let prepared = Program::prepare(
+        &request.lang,
+        lang_runner,
+        &request.code,
+        &compile_flags,
+        problem_files,
+        current_dir,
+        context,
+    )?;
+    let compile = prepared.compile;
+    let mut program = match prepared.program {
+        Ok(program) => program,
+        Err(state) => {
+            return Ok(failed_before_run(
+                unless_cancelled(state, cancellation),
+                compile,
+            ));
+        }
+    };
+    if let IoMode::File { output, .. } = &request.io_mode {
+        program.write_output_to(output);
+    }
+
+    let interactor = match &request.interactor {
+        Some(interactor) => {
+            let interactor_dir = create_sub_dir(root_dir, "interactor")?;
+            match CompiledInteractor::prepare(runners, interactor, interactor_dir, context)? {
+                Ok(interactor) => Some(interactor),
+                Err(state) => {
+                    return Ok(failed_before_run(
+                        unless_cancelled(state, cancellation),
+                        compile,
+                    ));
+                }
+            }
         }
+        None => None,
+    };
+
+    let checker = match request.checker.as_ref().filter(|_| interactor.is_none()) {
+        Some(checker) => {
+            let checker_dir = create_sub_dir(root_dir, "checker")?;
+            match CompiledChecker::prepare(runners, checker, checker_dir, context)? {
+                Ok(checker) => Some(checker),
+                Err(state) => {
+                    return Ok(failed_before_run(
+                        unless_cancelled(state, cancellation),
+                        compile,
+                    ));
+                }
+            }
+        }
+        None => None,
+    };
 
-        let mut command = builder.build();
-
-        command
-            .arg(SH_CMD)
-            .arg("-c")
-            .arg(compile_cmd)
-            .stderr(Stdio::piped());
-        log::debug!("Compile Command: {:?}", command);
-        let child = command.spawn()?;
-
-        let output = child.wait_with_output()?;
-        let (memory, time) = GTime::read(&current_dir)?;
-        log::debug!("Compile Memory: {:?}, Time: {:?}", memory, time);
-        // TODO: compile time limit and memory limit check
-        if !output.status.success() {
+    if cancellation.is_cancelled() {
+        return Ok(failed_before_run(RunnerState::Cancelled, compile));
+    }
+
+    let limits = limits(&request);
+    let total = resolved.len();
+    let mut test_cases = Vec::with_capacity(total);
+    let mut transcripts = Vec::new();
+    for (index, test_case) in resolved.iter().enumerate() {
+        log::debug!("Running test case {}", index);
+        on_event(RunEvent::Running { index, total });
+        let on_output = |pipe: Pipe, chunk: &[u8]| {
+            let data = String::from_utf8_lossy(chunk).to_string();
+            on_event(match pipe {
+                Pipe::Stdout => RunEvent::Stdout { index, data },
+                Pipe::Stderr => RunEvent::Stderr { index, data },
+            })
+        };
+        let (state, transcript) = match &interactor {
+            Some(interactor) => run_interactive_test_case(&program, interactor, test_case, limits)?,
+            None => (
+                run_test_case(
+                    &request,
+                    &program,
+                    checker.as_ref(),
+                    test_case,
+                    limits,
+                    &on_output,
+                )?,
+                None,
+            ),
+        };
+
+        // Whatever the killed test case ended with is not its verdict.
+        if cancellation.is_cancelled() {
             return Ok(RunnerResponse {
-                state: RunnerState::CompileError {
-                    stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-                },
+                state: RunnerState::Cancelled,
+                test_cases,
+                compile,
+                transcripts,
             });
         }
+
+        on_event(RunEvent::TestCase {
+            index,
+            state: state.clone(),
+        });
+        test_cases.push(state);
+        transcripts.extend(transcript);
     }
 
-    let run_cmd = match lang_runner.run_cmd() {
-        RunCommand::WithCode { run_cmd } => run_cmd(&request.code),
-        RunCommand::Static { run_cmd } => run_cmd.to_string(),
-    };
+    let state = RunnerState::aggregate(&test_cases).ok_or(Error::NoTestCases)?;
+
+    Ok(RunnerResponse {
+        state,
+        test_cases,
+        compile,
+        transcripts,
+    })
+}
+
+/// A request that [`check_request`] found nothing wrong with.
+pub struct CheckedRequest {
+    /// Without its test cases, which are in `test_cases`.
+    request: RunnerRequest,
+    /// The files of the submission besides its code, extracted.
+    files: Vec<SubmissionFile>,
+    problem_files: Vec<SubmissionFile>,
+    test_cases: Vec<ResolvedTestCase>,
+}
+
+/// Fails for whatever makes `request` impossible to run, so that it is refused up front.
+///
+/// Archives are extracted and inline test data decoded here, once, and kept for the run.
+pub fn check_request(
+    runners: &Runners,
+    mut request: RunnerRequest,
+    option: &RunnerOption,
+) -> Result<CheckedRequest> {
+    if request.test_cases.is_empty() {
+        return Err(Error::NoTestCases);
+    }
+    runners.check(&request)?;
+    let lang_runner = runners.get(&request.lang)?;
+    let files = files::prepare(&request, lang_runner, option)?;
+    let problem_files = ProblemFiles::check(&request, lang_runner, option)?;
+    files::check_io_mode(&request, lang_runner, &files)?;
+    let store = TestDataStore::new(option);
+    let test_cases = std::mem::take(&mut request.test_cases)
+        .into_iter()
+        .map(|test_case| store.resolve_test_case(test_case))
+        .collect::<Result<_>>()?;
+    Ok(CheckedRequest {
+        request,
+        files,
+        problem_files,
+        test_cases,
+    })
+}
+
+fn failed_before_run(state: RunnerState, compile: Option<CompileReport>) -> RunnerResponse {
+    RunnerResponse {
+        state,
+        test_cases: Vec::new(),
+        compile,
+        transcripts: Vec::new(),
+    }
+}
+
+/// `state`, unless it came from a sandbox killed by `cancellation`.
+fn unless_cancelled(state: RunnerState, cancellation: &Cancellation) -> RunnerState {
+    if cancellation.is_cancelled() {
+        RunnerState::Cancelled
+    } else {
+        state
+    }
+}
+
+fn limits(request: &RunnerRequest) -> Limits {
+    Limits {
+        wall_time: request.wall_time_limit(),
+        cpu_time: request.cpu_time_limit(),
+        memory: request.memory_limit,
+    }
+}
 
-    let mut builder = NsJailBuilder::new_with(GTime::new_cmd());
-    builder
-        .env("PATH", &bin_path)
-        .mount_ro(&lang_runner_path)
-        .time_limit(request.ms_time_limit.add_seconds(1))
-        .memory_limit(request.memory_limit.add_megabytes(1))
-        .log("nsjail.log")
-        .cwd(&current_dir);
-
-    if let Some(f) = lang_runner.option().more_run {
-        f(&mut builder)
-    }
-
-    let mut command = builder.build();
-    command.arg(SH_CMD).arg("-c").arg(run_cmd);
-    log::debug!("Run command: {:?}", command);
-    let mut child = command
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-    let stdin = child
-        .stdin
-        .as_mut()
-        .ok_or_else(|| Error::IO(std::io::Error::other("Failed to open stdin")))?;
-
-    stdin.write_all(request.stdin.as_bytes())?;
-
-    let output = child.wait_with_output()?;
-    let (memory, time) = GTime::read(&current_dir)?;
-    log::debug!("Run Memory: {:?}, Time: {:?}", memory, time);
-
-    if time > request.ms_time_limit {
-        return Ok(RunnerResponse {
-            state: RunnerState::Timeout {
-                ms_time_elapsed: time,
-            },
+fn limit_exceeded(limits: Limits, usage: Usage) -> Option<RunnerState> {
+    // Killed for it, whatever the peak it got to before.
+    if usage.oom_killed {
+        return Some(RunnerState::MemoryLimit {
+            max_memory_usage: usage.memory,
         });
     }
 
-    if memory > request.memory_limit {
-        return Ok(RunnerResponse {
-            state: RunnerState::MemoryLimit {
-                max_memory_usage: memory,
-            },
+    let cpu_time_exceeded = limits.cpu_time.is_some_and(|limit| usage.cpu_time > limit);
+    if cpu_time_exceeded || usage.time > limits.wall_time {
+        return Some(RunnerState::Timeout {
+            ms_time_elapsed: usage.time,
+            ms_cpu_time: usage.cpu_time,
         });
     }
 
-    if !output.status.success() {
-        return Ok(RunnerResponse {
-            state: RunnerState::RuntimeError {
-                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-                exit_code: output.status.code().unwrap_or(137),
-
-                max_memory_usage: memory,
-                ms_time_elapsed: time,
-            },
+    if usage.memory > limits.memory {
+        return Some(RunnerState::MemoryLimit {
+            max_memory_usage: usage.memory,
         });
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    Ok(RunnerResponse {
-        state: RunnerState::Success {
+    None
+}
+
+fn runtime_error(output: &Output, jail_log: &NsJailLog, usage: Usage) -> RunnerState {
+    let termination = Termination::new(output.status, jail_log);
+    RunnerState::RuntimeError {
+        stderr: CapturedOutput::new(output.stderr.clone()),
+        exit_code: termination.exit_code,
+        signal: termination.signal,
+        kind: termination.kind,
+
+        max_memory_usage: usage.memory,
+        ms_time_elapsed: usage.time,
+        ms_cpu_time: usage.cpu_time,
+    }
+}
+
+fn checker_state(verdict: CheckerVerdict, stdout: CapturedOutput, usage: Usage) -> RunnerState {
+    match verdict {
+        CheckerVerdict::Accepted(report) => RunnerState::Accepted {
+            max_memory_usage: usage.memory,
+            ms_time_elapsed: usage.time,
+            ms_cpu_time: usage.cpu_time,
+
+            checker: Some(report),
+        },
+        CheckerVerdict::WrongAnswer(report) => RunnerState::WrongAnswer {
             stdout,
+            diff_hint: report.message.clone(),
 
-            max_memory_usage: memory,
-            ms_time_elapsed: time,
+            max_memory_usage: usage.memory,
+            ms_time_elapsed: usage.time,
+            ms_cpu_time: usage.cpu_time,
+
+            checker: Some(report),
         },
-    })
+        CheckerVerdict::PartiallyAccepted(score, report) => RunnerState::PartiallyAccepted {
+            stdout,
+            score,
+
+            max_memory_usage: usage.memory,
+            ms_time_elapsed: usage.time,
+            ms_cpu_time: usage.cpu_time,
+
+            checker: report,
+        },
+        CheckerVerdict::Failed { message } => RunnerState::CheckerError { message },
+    }
+}
+
+fn run_test_case(
+    request: &RunnerRequest,
+    program: &Program,
+    checker: Option<&CompiledChecker>,
+    test_case: &ResolvedTestCase,
+    limits: Limits,
+    on_output: &(dyn Fn(Pipe, &[u8]) + Sync),
+) -> Result<RunnerState> {
+    let stdin: Box<dyn Read + Send> = match &request.io_mode {
+        IoMode::Stdio => test_case.input.reader()?,
+        IoMode::File { input, .. } => {
+            test_case.input.write_to(&program.dir().join(input))?;
+            Box::new(std::io::empty())
+        }
+    };
+    let execution = match program.run(stdin, limits, on_output)? {
+        Ok(execution) => execution,
+        Err(state) => return Ok(state),
+    };
+    let Execution {
+        mut output,
+        usage,
+        jail_log,
+    } = execution;
+
+    if let Some(state) = limit_exceeded(program.sandbox_limits(limits), usage) {
+        return Ok(state);
+    }
+
+    // The output file takes the place of stdout from here on. Writing past its size limit
+    // kills the program, which is an exceeded output limit rather than a runtime error.
+    if let Some(path) = program.output_file() {
+        let limit = program.output_limit();
+        let written = read_capped(
+            File::open(path)?.take(limit.as_bytes().saturating_add(1)),
+            limit,
+            None,
+            |_| {},
+        )?;
+        if written.exceeded {
+            return Ok(RunnerState::OutputLimitExceeded {
+                stdout: CapturedOutput::new(written.bytes),
+                stderr: CapturedOutput::new(output.stderr),
+            });
+        }
+        output.stdout = written.bytes;
+    }
+
+    if !output.status.success() {
+        return Ok(runtime_error(&output, &jail_log, usage));
+    }
+
+    if let Some(checker) = checker {
+        let verdict = checker.check(test_case, &output.stdout)?;
+        return Ok(checker_state(
+            verdict,
+            CapturedOutput::new(output.stdout),
+            usage,
+        ));
+    }
+
+    let stdout = CapturedOutput::new(output.stdout.clone());
+    let Some(expected_output) = &test_case.answer else {
+        return Ok(RunnerState::Success {
+            stdout,
+
+            max_memory_usage: usage.memory,
+            ms_time_elapsed: usage.time,
+            ms_cpu_time: usage.cpu_time,
+        });
+    };
+
+    match request
+        .compare_mode
+        .compare_bytes(&expected_output.read()?, &output.stdout)
+    {
+        Ok(()) => Ok(RunnerState::Accepted {
+            max_memory_usage: usage.memory,
+            ms_time_elapsed: usage.time,
+            ms_cpu_time: usage.cpu_time,
+
+            checker: None,
+        }),
+        Err(mismatch) => Ok(RunnerState::WrongAnswer {
+            stdout,
+            diff_hint: mismatch.to_string(),
+
+            max_memory_usage: usage.memory,
+            ms_time_elapsed: usage.time,
+            ms_cpu_time: usage.cpu_time,
+
+            checker: None,
+        }),
+    }
+}
+
+fn run_interactive_test_case(
+    program: &Program,
+    interactor: &CompiledInteractor,
+    test_case: &ResolvedTestCase,
+    limits: Limits,
+) -> Result<(RunnerState, Option<Transcript>)> {
+    let interaction = interactor.interact(program, test_case, limits)?;
+    let Execution {
+        output,
+        usage,
+        jail_log,
+    } = interaction.contestant;
+
+    if let Some(state) = limit_exceeded(program.sandbox_limits(limits), usage) {
+        return Ok((state, interaction.transcript));
+    }
+
+    // A contestant that crashes after being told it is wrong is still wrong,
+    // so the interactor rejecting it outranks the contestant's exit status.
+    let state = match interaction.verdict {
+        verdict @ (CheckerVerdict::Failed { .. } | CheckerVerdict::WrongAnswer(_)) => {
+            checker_state(verdict, CapturedOutput::new(Vec::new()), usage)
+        }
+        _ if !output.status.success() => runtime_error(&output, &jail_log, usage),
+        verdict => checker_state(verdict, CapturedOutput::new(Vec::new()), usage),
+    };
+
+    Ok((state, interaction.transcript))
 }
 
 fn create_dir_by_uid(uid: ulid::Ulid) -> Result<PathBuf> {
-    let current_dir = Path::new(RUNNING_PATH).join(uid.to_string());
-    std::fs::create_dir(&current_dir)?;
-    std::os::unix::fs::chown(&current_dir, Some(PERMISSION_ID), Some(PERMISSION_ID))?;
-    Ok(current_dir)
+    create_sub_dir(Path::new(RUNNING_PATH), &uid.to_string())
+}
+
+fn create_sub_dir(parent: &Path, name: &str) -> Result<PathBuf> {
+    let dir = parent.join(name);
+    std::fs::create_dir(&dir)?;
+    std::os::unix::fs::chown(&dir, Some(PERMISSION_ID), Some(PERMISSION_ID))?;
+    Ok(dir)
 }
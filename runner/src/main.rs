@@ -1,7 +1,20 @@
-use axum::{Json, Router, extract::State, routing::post};
+use std::convert::Infallible;
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{DefaultBodyLimit, Path, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post, put},
+};
 use envman::EnvMan;
-use runner::env::RunnerOption;
-use runner_schema::web::{RunnerRequest, RunnerResponse};
+use futures_util::{Stream, StreamExt};
+use runner::{env::RunnerOption, queue::JobQueue, testdata::TestDataStore};
+use runner_schema::web::{
+    LanguageInfo, QueueStatus, RunnerRequest, RunnerResponse, Submission, SubmissionCreated,
+    TestDataInfo,
+};
 
 struct RunnerState {
     pub option: RunnerOption,
@@ -15,21 +28,27 @@ async fn main() {
 
     log::info!("Starting runner...");
 
-    let option = RunnerOption::load().expect("Failed to load environment variables");
+    let mut option = RunnerOption::load().expect("Failed to load environment variables");
+    option.measurement = option.measurement.setup();
 
     log::info!("Runner environment: {:#?}", option);
 
-    let runners = match runner::runner::Runners::new() {
+    if let Err(err) = runner::workdir::sweep() {
+        log::warn!("Failed to remove stale run directories: {}", err);
+    }
+
+    if let Err(err) = TestDataStore::new(&option).setup() {
+        log::error!("Failed to set up the test data store: {}", err);
+        std::process::exit(1);
+    }
+
+    let runners = match runner::runner::Runners::new(&option.languages_path) {
         Ok(runners) => {
             log::info!("Runners initialized successfully");
             runners
         }
-        Err((lang, err)) => {
-            log::error!(
-                "Failed to initialize runner for language {:#?}: {:#?}",
-                lang,
-                err
-            );
+        Err(err) => {
+            log::error!("Failed to initialize runners: {}", err);
             std::process::exit(1);
         }
     };
@@ -40,9 +59,28 @@ async fn main() {
 
     let state: &'static RunnerState = STATE.get_or_init(|| state);
 
+    let queue = JobQueue::new(&state.runners, &state.option).expect("Failed to start workers");
+
+    static QUEUE: std::sync::OnceLock<JobQueue> = std::sync::OnceLock::new();
+
+    let queue: &'static JobQueue = QUEUE.get_or_init(|| queue);
+
     let app = Router::new()
         .route("/run", post(router_run))
-        .with_state(state);
+        .route("/run/stream", post(router_run_stream))
+        .route("/submissions", post(router_submit))
+        .route("/submissions/{id}", get(router_submission))
+        .route("/submissions/{id}/cancel", post(router_cancel))
+        .route("/queue", get(router_queue))
+        .route("/languages", get(router_languages))
+        .route("/testdata", get(router_testdata_list))
+        .route(
+            "/testdata/{sha256}",
+            put(router_testdata_put)
+                .delete(router_testdata_delete)
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .with_state(queue);
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
@@ -53,17 +91,145 @@ async fn main() {
 }
 
 async fn router_run(
-    State(state): State<&'static RunnerState>,
+    State(queue): State<&'static JobQueue>,
+    Json(payload): Json<RunnerRequest>,
+) -> Result<Json<RunnerResponse>, ErrorResponse> {
+    match queue.run(payload).await {
+        Ok(response) => Ok(Json(response)),
+        Err(err) => match refused(&err) {
+            Some(status) => Err((status, err.to_string())),
+            None => {
+                log::error!("Internal Error: {}", err);
+                Ok(Json(RunnerResponse::internal_error()))
+            }
+        },
+    }
+}
+
+/// Streams the [`runner_schema::web::RunEvent`]s of a run, one JSON object per event.
+async fn router_run_stream(
+    State(queue): State<&'static JobQueue>,
+    Json(payload): Json<RunnerRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ErrorResponse> {
+    let events = queue.stream(payload).await.map_err(error_response)?;
+    let stream = futures_util::stream::unfold(events, |mut events| async move {
+        let event = events.recv().await?;
+        let event = Event::default().json_data(&event).unwrap_or_else(|err| {
+            log::error!("Failed to serialize event: {}", err);
+            Event::default().comment("failed to serialize event")
+        });
+        Some((Ok(event), events))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+async fn router_submit(
+    State(queue): State<&'static JobQueue>,
     Json(payload): Json<RunnerRequest>,
-) -> Json<RunnerResponse> {
-    runner::run(&state.runners, payload, &state.option)
-        .map(Json)
-        .unwrap_or_else(|err| {
+) -> Result<Json<SubmissionCreated>, ErrorResponse> {
+    let id = queue.submit(payload).await.map_err(error_response)?;
+    Ok(Json(SubmissionCreated { id: id.to_string() }))
+}
+
+/// The status to turn a request away with for `err`,
+/// or `None` if the runner itself failed rather than refusing the request.
+fn refused(err: &runner::Error) -> Option<StatusCode> {
+    match err {
+        runner::Error::TestDataTooLarge(_) => Some(StatusCode::PAYLOAD_TOO_LARGE),
+        err if err.is_client_error() => Some(StatusCode::BAD_REQUEST),
+        runner::Error::QueueFull => Some(StatusCode::SERVICE_UNAVAILABLE),
+        runner::Error::InsufficientDisk { .. } => {
+            log::error!("Refused request: {}", err);
+            Some(StatusCode::INSUFFICIENT_STORAGE)
+        }
+        _ => None,
+    }
+}
+
+/// A status with a plain text body saying what was wrong.
+type ErrorResponse = (StatusCode, String);
+
+/// What to answer with for `err`, see [`refused`].
+///
+/// Refused requests are told why, internal errors are only logged.
+fn error_response(err: runner::Error) -> ErrorResponse {
+    match refused(&err) {
+        Some(status) => (status, err.to_string()),
+        None => {
             log::error!("Internal Error: {}", err);
-            Json(RunnerResponse {
-                state: runner_schema::state::RunnerState::InternalError,
-            })
-        })
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal error".to_string(),
+            )
+        }
+    }
+}
+
+async fn router_queue(State(queue): State<&'static JobQueue>) -> Json<QueueStatus> {
+    Json(queue.status())
+}
+
+async fn router_languages(State(queue): State<&'static JobQueue>) -> Json<Vec<LanguageInfo>> {
+    Json(queue.languages().to_vec())
+}
+
+async fn router_testdata_list(
+    State(queue): State<&'static JobQueue>,
+) -> Result<Json<Vec<TestDataInfo>>, ErrorResponse> {
+    queue.testdata().list().map(Json).map_err(error_response)
+}
+
+/// Stores the body as test data, streamed to disk, if it hashes to `sha256`.
+async fn router_testdata_put(
+    State(queue): State<&'static JobQueue>,
+    Path(sha256): Path<String>,
+    body: Body,
+) -> Result<StatusCode, ErrorResponse> {
+    let mut upload = queue.testdata().upload(&sha256).map_err(error_response)?;
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+        tokio::task::block_in_place(|| upload.write(&chunk)).map_err(error_response)?;
+    }
+    tokio::task::block_in_place(|| upload.finish()).map_err(error_response)?;
+    Ok(StatusCode::CREATED)
+}
+
+async fn router_testdata_delete(
+    State(queue): State<&'static JobQueue>,
+    Path(sha256): Path<String>,
+) -> Result<StatusCode, ErrorResponse> {
+    match queue.testdata().delete(&sha256).map_err(error_response)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Ok(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn router_submission(
+    State(queue): State<&'static JobQueue>,
+    Path(id): Path<String>,
+) -> Result<Json<Submission>, StatusCode> {
+    let id = ulid::Ulid::from_string(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    queue.get(id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Cancels a queued or running submission, which then finishes as
+/// [`runner_schema::state::RunnerState::Cancelled`].
+///
+/// `id` is what `POST /submissions` answered with, or what the first event of
+/// `POST /run/stream` carried. `POST /run` is cancelled by closing its connection.
+async fn router_cancel(
+    State(queue): State<&'static JobQueue>,
+    Path(id): Path<String>,
+) -> StatusCode {
+    let Ok(id) = ulid::Ulid::from_string(&id) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    if queue.cancel(id) {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::NOT_FOUND
+    }
 }
 
 #[allow(clippy::expect_used)]
@@ -1,8 +1,11 @@
-use std::{ffi::OsStr, path::Path, process::Command};
+use std::{ffi::OsStr, os::unix::process::CommandExt, path::Path, process::Command};
 
 use runner_schema::{memory::Memory, time::MsTime};
 
-use crate::env::{NIX_BIN, NIX_STORE_PATH, NSJAIL_CMD, PERMISSION_ID_STR};
+use crate::{
+    affinity::CpuSet,
+    env::{NIX_BIN, NIX_STORE_PATH, NSJAIL_CMD, PERMISSION_ID_STR},
+};
 
 pub struct NsJailBuilder {
     command: Command,
@@ -43,6 +46,15 @@ impl NsJailBuilder {
         self
     }
 
+    /// Limits CPU time with `RLIMIT_CPU`, so the process gets `SIGXCPU` past it.
+    pub fn cpu_time_limit(&mut self, cpu_time_limit: MsTime) -> &mut Self {
+        self.command
+            .arg("--rlimit_cpu")
+            .arg(cpu_time_limit.as_seconds_ceil().to_string());
+
+        self
+    }
+
     pub fn memory_limit(&mut self, memory_limit: Memory) -> &mut Self {
         self.command
             .arg("--cgroup_mem_max")
@@ -51,6 +63,26 @@ impl NsJailBuilder {
         self
     }
 
+    /// Replaces the default `RLIMIT_AS`, as nsjail takes the last of repeated options.
+    pub fn address_space_limit(&mut self, limit: Memory) -> &mut Self {
+        self.command
+            .arg("--rlimit_as")
+            .arg(limit.as_megabytes().to_string());
+
+        self
+    }
+
+    /// Pins nsjail, and so the jailed process, to `cpu`.
+    pub fn cpu_affinity(&mut self, cpu: usize) -> &mut Self {
+        let cpu_set = CpuSet::new([cpu]);
+        // SAFETY: `apply` is async-signal-safe and `cpu_set` was built before fork.
+        unsafe {
+            self.command.pre_exec(move || cpu_set.apply());
+        }
+
+        self
+    }
+
     pub fn env(&mut self, key: &str, value: &str) -> &mut Self {
         self.command.arg("--env").arg(format!("{}={}", key, value));
 
@@ -125,8 +157,11 @@ impl NsJailBuilder {
         self
     }
 
+    /// The command is spawned as the leader of a new process group,
+    /// so the whole sandbox can be killed with [`crate::program::kill_process_group`].
     pub fn build(self) -> Command {
         let mut command = self.command;
+        command.process_group(0);
 
         match self.proc_writable {
             Some(true) => {
@@ -164,3 +199,99 @@ impl NsJailBuilder {
         command.arg("-R").arg(NIX_BIN);
     }
 }
+
+/// What nsjail logged about how the jailed process ended.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NsJailLog {
+    /// The signal that terminated the process.
+    pub signal: Option<i32>,
+    /// The status the process exited with, if it exited rather than being killed.
+    pub exit_status: Option<i32>,
+    /// Whether nsjail killed the process for running past `--time_limit`.
+    pub time_limit_exceeded: bool,
+}
+
+impl NsJailLog {
+    pub const LOG: &'static str = "nsjail.log";
+
+    /// Reads the log written by [`NsJailBuilder::log`] with [`NsJailLog::LOG`],
+    /// which is empty if nsjail did not get to write one.
+    pub fn read(parent_dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        match std::fs::read_to_string(parent_dir.as_ref().join(Self::LOG)) {
+            Ok(log) => Ok(Self::parse(&log)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn parse(log: &str) -> Self {
+        let mut parsed = Self::default();
+        for line in log.lines() {
+            if line.contains("run time >= time limit") {
+                parsed.time_limit_exceeded = true;
+            }
+            // e.g. `pid=2 ([STANDALONE MODE]) terminated with signal: SIGSEGV (11), (PIDs left: 0)`
+            if let Some((_, rest)) = line.split_once("terminated with signal: ")
+                && let Some((_, rest)) = rest.split_once('(')
+                && let Some((number, _)) = rest.split_once(')')
+                && let Ok(number) = number.parse()
+            {
+                parsed.signal = Some(number);
+            }
+            // e.g. `pid=2 ([STANDALONE MODE]) exited with status: 1, (PIDs left: 0)`
+            if let Some((_, rest)) = line.split_once("exited with status: ")
+                && let Some((status, _)) = rest.split_once(',')
+                && let Ok(status) = status.parse()
+            {
+                parsed.exit_status = Some(status);
+            }
+        }
+        parsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_signal() {
+        let log = "[I][2025-05-20T10:00:00+0000] Mode: STANDALONE_ONCE\n\
+            [I][2025-05-20T10:00:01+0000] pid=2 ([STANDALONE MODE]) terminated with signal: SIGSEGV (11), (PIDs left: 0)\n";
+        assert_eq!(
+            NsJailLog::parse(log),
+            NsJailLog {
+                signal: Some(11),
+                exit_status: None,
+                time_limit_exceeded: false,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_time_limit() {
+        let log = "[I][2025-05-20T10:00:02+0000] pid=2 run time >= time limit (2 >= 2) (./main). Killing it\n\
+            [I][2025-05-20T10:00:02+0000] pid=2 ([STANDALONE MODE]) terminated with signal: SIGKILL (9), (PIDs left: 0)\n";
+        assert_eq!(
+            NsJailLog::parse(log),
+            NsJailLog {
+                signal: Some(9),
+                exit_status: None,
+                time_limit_exceeded: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_exited() {
+        let log = "[I][2025-05-20T10:00:00+0000] pid=2 ([STANDALONE MODE]) exited with status: 139, (PIDs left: 0)\n";
+        assert_eq!(
+            NsJailLog::parse(log),
+            NsJailLog {
+                signal: None,
+                exit_status: Some(139),
+                time_limit_exceeded: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,578 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Child, ExitStatus, Output, Stdio},
+    time::Instant,
+};
+
+use runner_schema::{
+    Language,
+    data::CapturedOutput,
+    memory::Memory,
+    state::{CompileReport, RunnerState},
+    time::MsTime,
+};
+
+use crate::{
+    Error, Result,
+    cancel::Cancellation,
+    env::{PERMISSION_ID, RunnerOption, SH_CMD},
+    files::ProblemFiles,
+    lang::LangExt,
+    nsjail::NsJailLog,
+    runner::{
+        LangRunner, expand,
+        java::{DEFAULT_CLASS, public_class},
+    },
+    time::{Meter, Usage},
+    workdir,
+};
+
+/// Source code written to its own directory and compiled, ready to be run.
+pub struct Program<'a> {
+    lang: &'a Language,
+    lang_runner: &'a LangRunner,
+    /// The run command with `{class}` filled in.
+    run_cmd: String,
+    dir: PathBuf,
+    problem_files: Option<ProblemFiles>,
+    /// The file in `dir` every run writes its output to instead of stdout, if any.
+    output_file: Option<String>,
+    output_limit: Memory,
+    cpu: Option<usize>,
+    cancellation: Cancellation,
+    meter: Box<dyn Meter>,
+}
+
+/// What every sandbox of one run is spawned with.
+#[derive(Clone, Copy)]
+pub struct RunContext<'a> {
+    pub option: &'a RunnerOption,
+    /// Caps each of stdout and stderr, both while compiling and when run.
+    pub output_limit: Memory,
+    /// The CPU every sandbox is pinned to, if any.
+    pub cpu: Option<usize>,
+    pub cancellation: &'a Cancellation,
+}
+
+/// The outcome of [`Program::prepare`].
+pub struct Prepared<'a> {
+    /// The program, or the state to report when compilation failed.
+    pub program: std::result::Result<Program<'a>, RunnerState>,
+    /// What compiling used, if the language compiles and the compiler was not killed
+    /// for writing too much.
+    pub compile: Option<CompileReport>,
+}
+
+/// Limits for one run of a [`Program`].
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub wall_time: MsTime,
+    pub cpu_time: Option<MsTime>,
+    pub memory: Memory,
+}
+
+pub struct Execution {
+    pub output: Output,
+    pub usage: Usage,
+    pub jail_log: NsJailLog,
+}
+
+/// A spawned [`Program`], timed by the wall clock from when it was spawned.
+///
+/// It can be killed through the [`Cancellation`] it was spawned with until it is dropped.
+pub struct Sandbox {
+    pub child: Child,
+    started: Instant,
+    cancellation: Cancellation,
+}
+
+impl Sandbox {
+    fn new(child: Child, cancellation: &Cancellation) -> Self {
+        cancellation.register(child.id());
+        Self {
+            child,
+            started: Instant::now(),
+            cancellation: cancellation.clone(),
+        }
+    }
+
+    /// Waits for the sandbox to exit, returning its status and how long it ran for.
+    pub fn wait(&mut self) -> std::io::Result<(ExitStatus, MsTime)> {
+        let status = self.child.wait()?;
+        let elapsed = u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        Ok((status, MsTime::new_ms(elapsed)))
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        self.cancellation.unregister(self.child.id());
+    }
+}
+
+impl<'a> Program<'a> {
+    /// Writes `code` into `dir` and compiles it, if the language needs it.
+    ///
+    /// `{class}` in the language's file name and commands is the public class of `code`,
+    /// see [`public_class`], and `{options}` is `compile_flags`, see [`LangRunner::compile_flags`].
+    ///
+    /// `problem_files` are mounted into every sandbox of the program,
+    /// which is then compiled and run with the grader commands of its language.
+    pub fn prepare(
+        lang: &'a Language,
+        lang_runner: &'a LangRunner,
+        code: &str,
+        compile_flags: &str,
+        problem_files: Option<ProblemFiles>,
+        dir: PathBuf,
+        context: RunContext,
+    ) -> Result<Prepared<'a>> {
+        let graded = problem_files.is_some();
+        let class = public_class(code).unwrap_or(DEFAULT_CLASS);
+        let vars = [("class", class), ("options", compile_flags)];
+
+        // The entry point may have come with the other files of the submission instead.
+        let path = dir.join(lang_runner.entry_point(code));
+        if !code.is_empty() || !path.exists() {
+            log::debug!("Writing to File: {}", path.display());
+            std::fs::write(path, code)?;
+        }
+
+        let meter = context.option.measurement.meter(&dir);
+
+        let (failure, compile) = match lang_runner.compile_cmd_for(graded) {
+            Some(compile_cmd) => {
+                let compilation = run_compile(
+                    lang,
+                    lang_runner,
+                    &expand(compile_cmd, &vars),
+                    problem_files.as_ref(),
+                    &dir,
+                    meter.as_ref(),
+                    context,
+                )?;
+                (compilation.failure, compilation.report)
+            }
+            None => (None, None),
+        };
+
+        if let Some(state) = failure {
+            return Ok(Prepared {
+                program: Err(state),
+                compile,
+            });
+        }
+
+        Ok(Prepared {
+            program: Ok(Self {
+                lang,
+                lang_runner,
+                run_cmd: expand(lang_runner.run_cmd_for(graded), &vars),
+                dir,
+                problem_files,
+                output_file: None,
+                output_limit: context.output_limit,
+                cpu: context.cpu,
+                cancellation: context.cancellation.clone(),
+                meter,
+            }),
+            compile,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn output_limit(&self) -> Memory {
+        self.output_limit
+    }
+
+    /// Gives every run an empty file `name` in its working directory to write to,
+    /// which is otherwise read-only, and caps its size a little over the output limit.
+    pub fn write_output_to(&mut self, name: &str) {
+        self.output_file = Some(name.to_string());
+    }
+
+    /// Where the last run wrote its output to, see [`Program::write_output_to`].
+    pub fn output_file(&self) -> Option<PathBuf> {
+        self.output_file.as_ref().map(|name| self.dir.join(name))
+    }
+
+    pub fn run(
+        &self,
+        stdin: impl Read + Send,
+        limits: Limits,
+        on_output: &(dyn Fn(Pipe, &[u8]) + Sync),
+    ) -> Result<std::result::Result<Execution, RunnerState>> {
+        self.run_with_args(&[], stdin, limits, on_output)
+    }
+
+    /// Runs the program with `args` appended to its run command,
+    /// calling `on_output` with every chunk of output as it is read.
+    ///
+    /// Returns [`RunnerState::OutputLimitExceeded`] if it wrote more than the output limit,
+    /// in which case the sandbox is killed and nothing is measured.
+    pub fn run_with_args(
+        &self,
+        args: &[&str],
+        stdin: impl Read + Send,
+        limits: Limits,
+        on_output: &(dyn Fn(Pipe, &[u8]) + Sync),
+    ) -> Result<std::result::Result<Execution, RunnerState>> {
+        let mut sandbox = self.spawn(args, limits)?;
+        let pid = sandbox.child.id();
+
+        let child_stdin = take_pipe(sandbox.child.stdin.take())?;
+        let child_stdout = take_pipe(sandbox.child.stdout.take())?;
+        let child_stderr = take_pipe(sandbox.child.stderr.take())?;
+        let limit = self.output_limit;
+
+        let ((status, elapsed), stdout, stderr) = std::thread::scope(|scope| {
+            scope.spawn(move || {
+                let (mut stdin, mut child_stdin) = (stdin, child_stdin);
+                // The program may exit without reading all of its input.
+                let _ = std::io::copy(&mut stdin, &mut child_stdin);
+            });
+            let stdout = scope.spawn(move || {
+                read_capped(child_stdout, limit, Some(pid), |chunk| {
+                    on_output(Pipe::Stdout, chunk)
+                })
+            });
+            let stderr = scope.spawn(move || {
+                read_capped(child_stderr, limit, Some(pid), |chunk| {
+                    on_output(Pipe::Stderr, chunk)
+                })
+            });
+
+            let status = sandbox.wait()?;
+
+            Ok::<_, Error>((status, join(stdout)?, join(stderr)?))
+        })?;
+
+        if stdout.exceeded || stderr.exceeded {
+            log::debug!("Output limit {} exceeded", limit);
+            return Ok(Err(RunnerState::OutputLimitExceeded {
+                stdout: CapturedOutput::new(stdout.bytes),
+                stderr: CapturedOutput::new(stderr.bytes),
+            }));
+        }
+
+        Ok(Ok(Execution {
+            output: Output {
+                status,
+                stdout: stdout.bytes,
+                stderr: stderr.bytes,
+            },
+            usage: self.measure(elapsed)?,
+            jail_log: self.jail_log()?,
+        }))
+    }
+
+    /// The limits the program runs with when `limits` are requested,
+    /// which its usage is to be judged against.
+    pub fn sandbox_limits(&self, limits: Limits) -> Limits {
+        self.lang_runner.option().adjust(limits)
+    }
+
+    /// Spawns the program in its sandbox with every standard stream piped.
+    ///
+    /// `{memory_mb}` in the run command is the requested memory limit in megabytes
+    /// and `{stack_mb}` a quarter of it, up to 256, for runtimes that need to be told.
+    ///
+    /// Call [`Program::measure`] once the sandbox has exited.
+    pub fn spawn(&self, args: &[&str], limits: Limits) -> Result<Sandbox> {
+        let memory_mb = limits.memory.as_megabytes().max(1);
+        let stack_mb = (memory_mb / 4).clamp(1, 256);
+        let mut run_cmd = expand(
+            &self.run_cmd,
+            &[
+                ("memory_mb", &memory_mb.to_string()),
+                ("stack_mb", &stack_mb.to_string()),
+            ],
+        );
+        let limits = self.sandbox_limits(limits);
+
+        workdir::clear_report(&self.dir.join(NsJailLog::LOG))?;
+
+        let mut builder = self.meter.start()?;
+        builder
+            .env("PATH", &self.lang.bin_path())
+            .mount_ro(&self.lang.runner_path())
+            .time_limit(limits.wall_time.add_seconds(1))
+            .memory_limit(limits.memory.add_megabytes(1))
+            .log(NsJailLog::LOG)
+            .cwd(&self.dir);
+
+        if let Some(cpu) = self.cpu {
+            builder.cpu_affinity(cpu);
+        }
+
+        if let Some(cpu_time) = limits.cpu_time {
+            builder.cpu_time_limit(cpu_time.add_seconds(1));
+        }
+
+        let option = self.lang_runner.option();
+        option.more_run.apply(&mut builder);
+        option.apply_address_space_limit(&mut builder);
+        if let Some(problem_files) = &self.problem_files {
+            problem_files.mount(&mut builder);
+        }
+        if let Some(name) = &self.output_file {
+            let path = self.dir.join(name);
+            std::fs::write(&path, "")?;
+            std::os::unix::fs::chown(&path, Some(PERMISSION_ID), Some(PERMISSION_ID))?;
+            builder
+                .mount_rw_dest(&path.to_string_lossy(), &format!("/{name}"))
+                .arg("--rlimit_fsize")
+                .arg((self.output_limit.as_megabytes() + 1).to_string());
+        }
+
+        for arg in args {
+            run_cmd.push(' ');
+            run_cmd.push_str(arg);
+        }
+
+        let mut command = builder.build();
+        command.arg(SH_CMD).arg("-c").arg(run_cmd);
+        log::debug!("Run command: {:?}", command);
+        let child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        Ok(Sandbox::new(child, &self.cancellation))
+    }
+
+    /// Reads the memory and time used by the last run, which took `elapsed` by the wall clock.
+    pub fn measure(&self, elapsed: MsTime) -> Result<Usage> {
+        let usage = self.meter.read(elapsed)?;
+        log::debug!("Run Usage: {:?}", usage);
+        Ok(usage)
+    }
+
+    /// Reads what nsjail logged about the last run.
+    pub fn jail_log(&self) -> Result<NsJailLog> {
+        Ok(NsJailLog::read(&self.dir)?)
+    }
+}
+
+struct Compilation {
+    failure: Option<RunnerState>,
+    report: Option<CompileReport>,
+}
+
+fn run_compile(
+    lang: &Language,
+    lang_runner: &LangRunner,
+    compile_cmd: &str,
+    problem_files: Option<&ProblemFiles>,
+    current_dir: &Path,
+    meter: &dyn Meter,
+    context: RunContext,
+) -> Result<Compilation> {
+    log::debug!("Compile command: {}", compile_cmd);
+    let option = context.option;
+
+    let mut builder = meter.start()?;
+    builder
+        .time_limit(option.compile_time_limit_seconds)
+        .memory_limit(option.compile_memory_limit_megabytes)
+        .proc_writable(true)
+        .arg("--rlimit_fsize")
+        .arg("100")
+        .arg("--rlimit_nofile")
+        .arg("128")
+        .cwd(current_dir)
+        .env("PATH", &lang.bin_path())
+        .mount_ro(&lang.runner_path())
+        .tmpfsmount("/tmp", Memory::new_megabytes(512))
+        .writable();
+
+    if let Some(cpu) = context.cpu {
+        builder.cpu_affinity(cpu);
+    }
+
+    lang_runner.option().more_compile.apply(&mut builder);
+    lang_runner.option().apply_address_space_limit(&mut builder);
+    if let Some(problem_files) = problem_files {
+        problem_files.mount(&mut builder);
+    }
+
+    let mut command = builder.build();
+
+    command
+        .arg(SH_CMD)
+        .arg("-c")
+        .arg(compile_cmd)
+        .stderr(Stdio::piped());
+    log::debug!("Compile Command: {:?}", command);
+    let mut sandbox = Sandbox::new(command.spawn()?, context.cancellation);
+
+    let stderr = read_capped(
+        take_pipe(sandbox.child.stderr.take())?,
+        context.output_limit,
+        Some(sandbox.child.id()),
+        |_| {},
+    )?;
+    let (status, elapsed) = sandbox.wait()?;
+    let stderr_text = String::from_utf8_lossy(&stderr.bytes).to_string();
+    if stderr.exceeded {
+        return Ok(Compilation {
+            failure: Some(RunnerState::CompileError {
+                stderr: stderr_text,
+            }),
+            report: None,
+        });
+    }
+
+    let usage = meter.read(elapsed)?;
+    log::debug!("Compile Usage: {:?}", usage);
+    let report = Some(CompileReport {
+        max_memory_usage: usage.memory,
+        ms_time_elapsed: usage.time,
+        ms_cpu_time: usage.cpu_time,
+    });
+
+    let failure = if usage.time >= option.compile_time_limit_seconds {
+        Some(RunnerState::CompileTimeout {
+            ms_time_elapsed: usage.time,
+        })
+    } else if usage.oom_killed || usage.memory >= option.compile_memory_limit_megabytes {
+        Some(RunnerState::CompileMemoryLimit {
+            max_memory_usage: usage.memory,
+        })
+    } else if !status.success() {
+        Some(RunnerState::CompileError {
+            stderr: stderr_text,
+        })
+    } else {
+        None
+    };
+
+    Ok(Compilation { failure, report })
+}
+
+/// Bytes read from a pipe, cut off at a limit.
+pub struct Capped {
+    pub bytes: Vec<u8>,
+    pub exceeded: bool,
+}
+
+/// Which pipe a chunk of output was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pipe {
+    Stdout,
+    Stderr,
+}
+
+/// Reads `from` to the end, keeping at most `limit` bytes
+/// and passing each chunk that is kept to `on_chunk` as it is read.
+///
+/// Once the limit is exceeded the process group of `kill` is killed, if given,
+/// and the rest is drained so the writer never blocks on a full pipe.
+pub fn read_capped(
+    mut from: impl Read,
+    limit: Memory,
+    kill: Option<u32>,
+    mut on_chunk: impl FnMut(&[u8]),
+) -> std::io::Result<Capped> {
+    let limit = usize::try_from(limit.as_bytes()).unwrap_or(usize::MAX);
+    let mut bytes = Vec::new();
+    let mut buffer = [0; 8192];
+
+    loop {
+        let read = match from.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+
+        let kept = read.min(limit - bytes.len());
+        if kept > 0 {
+            on_chunk(&buffer[..kept]);
+            bytes.extend_from_slice(&buffer[..kept]);
+        }
+
+        if kept < read {
+            if let Some(pid) = kill {
+                kill_process_group(pid);
+            }
+            std::io::copy(&mut from, &mut std::io::sink())?;
+            return Ok(Capped {
+                bytes,
+                exceeded: true,
+            });
+        }
+    }
+
+    Ok(Capped {
+        bytes,
+        exceeded: false,
+    })
+}
+
+/// Kills the process group led by `pid`.
+///
+/// Every sandbox is spawned as a group leader (see [`NsJailBuilder::build`]),
+/// and nsjail takes the jailed process down with it.
+pub fn kill_process_group(pid: u32) {
+    let Ok(pid) = i32::try_from(pid) else {
+        return;
+    };
+    // SAFETY: kill(2) does not touch our memory.
+    unsafe {
+        libc::kill(-pid, libc::SIGKILL);
+    }
+}
+
+pub(crate) fn join<T>(handle: std::thread::ScopedJoinHandle<'_, std::io::Result<T>>) -> Result<T> {
+    handle
+        .join()
+        .map_err(|_| Error::IO(std::io::Error::other("pipe reader panicked")))?
+        .map_err(Error::IO)
+}
+
+pub(crate) fn take_pipe<T>(pipe: Option<T>) -> Result<T> {
+    pipe.ok_or_else(|| Error::IO(std::io::Error::other("Failed to open pipe")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Reads `len` bytes capped at 10000, spanning more than one read buffer,
+    /// and how many bytes were passed on as chunks.
+    fn capped(len: usize) -> (Capped, usize, Cursor<Vec<u8>>) {
+        let input: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let mut reader = Cursor::new(input);
+        let mut chunked = 0;
+        let capped = read_capped(&mut reader, Memory::new_bytes(10000), None, |chunk| {
+            chunked += chunk.len()
+        })
+        .unwrap();
+        (capped, chunked, reader)
+    }
+
+    #[test]
+    fn read_capped_limits() {
+        for len in [0, 9999, 10000] {
+            let (capped, chunked, reader) = capped(len);
+            assert!(!capped.exceeded, "{len}");
+            assert_eq!(capped.bytes.as_slice(), &reader.get_ref()[..], "{len}");
+            assert_eq!(chunked, len);
+        }
+
+        let (capped, chunked, reader) = capped(30000);
+        assert!(capped.exceeded);
+        assert_eq!(capped.bytes.as_slice(), &reader.get_ref()[..10000]);
+        assert_eq!(chunked, 10000);
+        // The rest is drained rather than left in the pipe.
+        assert_eq!(reader.position(), 30000);
+    }
+}
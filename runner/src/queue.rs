@@ -0,0 +1,490 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use runner_schema::web::{
+    LanguageInfo, QueueStatus, RunEvent, RunnerRequest, RunnerResponse, Submission,
+    SubmissionStatus,
+};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    oneshot,
+};
+use ulid::Ulid;
+
+use crate::{
+    CheckedRequest, Error, Result,
+    affinity::CpuSet,
+    cancel::{CancelOnDrop, Cancellation},
+    env::RunnerOption,
+    runner::Runners,
+    testdata::TestDataStore,
+};
+
+struct Job {
+    id: Ulid,
+    request: CheckedRequest,
+    reply: Reply,
+    cancellation: Cancellation,
+}
+
+/// How the outcome of a [`Job`] gets back to whoever queued it.
+enum Reply {
+    /// Kept to be polled with [`JobQueue::get`].
+    Poll,
+    /// Sent once it is done.
+    Once(oneshot::Sender<RunnerResponse>),
+    /// Every [`RunEvent`] is sent as it happens.
+    Stream(mpsc::UnboundedSender<RunEvent>),
+}
+
+/// Submissions waiting for, or being run by, a fixed pool of blocking worker threads.
+///
+/// Every run goes through here, so no more than [`RunnerOption::workers`] sandboxes
+/// compete for the machine at once.
+pub struct JobQueue {
+    sender: mpsc::Sender<Job>,
+    runners: &'static Runners,
+    submissions: Arc<Submissions>,
+    option: &'static RunnerOption,
+}
+
+impl JobQueue {
+    /// Starts [`RunnerOption::workers`] threads that take jobs from a queue
+    /// holding at most [`RunnerOption::queue_capacity`].
+    ///
+    /// When there are at least as many CPUs as workers, each worker pins its sandboxes
+    /// to a CPU of its own so that their times do not depend on what else is running.
+    pub fn new(runners: &'static Runners, option: &'static RunnerOption) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel(option.queue_capacity.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let submissions = Arc::new(Submissions::default());
+
+        let cpus = CpuSet::current()?.cpus();
+        let pinned = cpus.len() >= option.workers;
+        if !pinned {
+            log::warn!(
+                "{} workers share {} CPUs, so they are not pinned",
+                option.workers,
+                cpus.len()
+            );
+        }
+
+        for index in 0..option.workers {
+            let cpu = cpus.get(index).copied().filter(|_| pinned);
+            let receiver = Arc::clone(&receiver);
+            let submissions = Arc::clone(&submissions);
+            std::thread::Builder::new()
+                .name(format!("runner-worker-{index}"))
+                .spawn(move || work(runners, option, cpu, &receiver, &submissions))?;
+        }
+
+        Ok(Self {
+            sender,
+            runners,
+            submissions,
+            option,
+        })
+    }
+
+    /// Queues `request` to be polled with [`JobQueue::get`], returning its id.
+    ///
+    /// Fails with [`Error::QueueFull`] rather than waiting for room.
+    pub async fn submit(&self, request: RunnerRequest) -> Result<Ulid> {
+        let (id, _) = self.enqueue(request, Reply::Poll).await?;
+        Ok(id)
+    }
+
+    /// Queues `request` and waits for it to finish.
+    ///
+    /// Dropping the returned future cancels the run.
+    pub async fn run(&self, request: RunnerRequest) -> Result<RunnerResponse> {
+        let (reply, response) = oneshot::channel();
+        let (_, cancellation) = self.enqueue(request, Reply::Once(reply)).await?;
+        let _cancel = CancelOnDrop(cancellation);
+        response.await.map_err(|_| Error::QueueClosed)
+    }
+
+    /// Queues `request`, returning the events of its run as they happen,
+    /// starting with [`RunEvent::Queued`] and ending with [`RunEvent::Finished`].
+    ///
+    /// Events are never waited on, so that a slow reader cannot hold up the sandbox.
+    /// Dropping the returned [`RunEvents`] cancels the run.
+    pub async fn stream(&self, request: RunnerRequest) -> Result<RunEvents> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (_, cancellation) = self.enqueue(request, Reply::Stream(sender)).await?;
+        Ok(RunEvents {
+            receiver,
+            _cancel: CancelOnDrop(cancellation),
+        })
+    }
+
+    /// Cancels the queued or running submission with `id`,
+    /// returning whether there was one to cancel.
+    pub fn cancel(&self, id: Ulid) -> bool {
+        self.submissions.cancel(id)
+    }
+
+    /// The submission with `id`, if it is known.
+    pub fn get(&self, id: Ulid) -> Option<Submission> {
+        self.submissions.get(id)
+    }
+
+    /// The languages requests may be in.
+    pub fn languages(&self) -> &[LanguageInfo] {
+        self.runners.languages()
+    }
+
+    /// The test data requests may refer to.
+    pub fn testdata(&self) -> TestDataStore<'static> {
+        TestDataStore::new(self.option)
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        QueueStatus {
+            queued: self.sender.max_capacity() - self.sender.capacity(),
+            running: self.submissions.running.load(Ordering::Relaxed),
+            workers: self.option.workers,
+            capacity: self.sender.max_capacity(),
+        }
+    }
+
+    async fn enqueue(&self, request: RunnerRequest, reply: Reply) -> Result<(Ulid, Cancellation)> {
+        // Decoding test data and extracting archives blocks, so it is kept off the async workers.
+        let (runners, option) = (self.runners, self.option);
+        let request = match tokio::task::spawn_blocking(move || {
+            crate::check_request(runners, request, option)
+        })
+        .await
+        {
+            Ok(checked) => checked?,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            // The runtime is shutting down.
+            Err(_) => return Err(Error::QueueClosed),
+        };
+
+        crate::workdir::check_free_space(self.option)?;
+
+        self.submissions.push(&self.sender, request, reply)
+    }
+}
+
+fn work(
+    runners: &Runners,
+    option: &RunnerOption,
+    cpu: Option<usize>,
+    receiver: &Mutex<mpsc::Receiver<Job>>,
+    submissions: &Submissions,
+) {
+    loop {
+        // Only one idle worker waits on the channel, the others wait for the lock.
+        let job = receiver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .blocking_recv();
+        let Some(Job {
+            id,
+            request,
+            reply,
+            cancellation,
+        }) = job
+        else {
+            return;
+        };
+
+        let on_event = |event: RunEvent| {
+            if let Some(status) = status_after(&event) {
+                submissions.set(id, status);
+            }
+            if let Reply::Stream(sender) = &reply {
+                // The reader may have gone away, in which case nobody wants the event.
+                let _ = sender.send(event);
+            }
+        };
+
+        submissions.running.fetch_add(1, Ordering::Relaxed);
+        let response =
+            crate::run_submission(id, runners, request, option, cpu, &cancellation, &on_event)
+                .unwrap_or_else(|err| {
+                    log::error!("Internal Error in {}: {}", id, err);
+                    RunnerResponse::internal_error()
+                });
+        submissions.running.fetch_sub(1, Ordering::Relaxed);
+        submissions.forget_cancellation(id);
+
+        match reply {
+            Reply::Poll => submissions.finish(id, response),
+            Reply::Once(reply) => {
+                submissions.remove(id);
+                // The caller may have gone away, in which case nobody wants the response.
+                let _ = reply.send(response);
+            }
+            Reply::Stream(sender) => {
+                submissions.remove(id);
+                let _ = sender.send(RunEvent::Finished(response));
+            }
+        }
+    }
+}
+
+/// The events of a streamed run, see [`JobQueue::stream`].
+pub struct RunEvents {
+    receiver: mpsc::UnboundedReceiver<RunEvent>,
+    _cancel: CancelOnDrop,
+}
+
+impl RunEvents {
+    /// The next event, or `None` after [`RunEvent::Finished`].
+    pub async fn recv(&mut self) -> Option<RunEvent> {
+        self.receiver.recv().await
+    }
+}
+
+/// The status a submission moves on to with `event`, if it moves on at all.
+fn status_after(event: &RunEvent) -> Option<SubmissionStatus> {
+    match event {
+        RunEvent::Compiling => Some(SubmissionStatus::Compiling),
+        RunEvent::Running { index: 0, .. } => Some(SubmissionStatus::Running),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Submissions {
+    inner: Mutex<SubmissionsInner>,
+    /// How many jobs workers are running right now.
+    running: AtomicUsize,
+}
+
+#[derive(Default)]
+struct SubmissionsInner {
+    statuses: HashMap<Ulid, (SubmissionStatus, Option<RunnerResponse>)>,
+    /// Finished submissions, oldest first, so they can be forgotten.
+    finished: VecDeque<Ulid>,
+    /// Cancels the submissions that have not finished yet.
+    cancellations: HashMap<Ulid, Cancellation>,
+}
+
+impl Submissions {
+    /// How many finished submissions are kept around to be polled.
+    const MAX_FINISHED: usize = 1024;
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SubmissionsInner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sends a job for `request` to the workers through `sender`,
+    /// failing with [`Error::QueueFull`] rather than waiting for room.
+    fn push(
+        &self,
+        sender: &mpsc::Sender<Job>,
+        request: CheckedRequest,
+        reply: Reply,
+    ) -> Result<(Ulid, Cancellation)> {
+        let id = Ulid::new();
+        let cancellation = Cancellation::default();
+        self.queue(id, cancellation.clone());
+        if let Reply::Stream(sender) = &reply {
+            // Sent before a worker can get to the job, so that it comes first.
+            let _ = sender.send(RunEvent::Queued { id: id.to_string() });
+        }
+        let job = Job {
+            id,
+            request,
+            reply,
+            cancellation: cancellation.clone(),
+        };
+        match sender.try_send(job) {
+            Ok(()) => Ok((id, cancellation)),
+            Err(err) => {
+                self.remove(id);
+                Err(match err {
+                    TrySendError::Full(_) => Error::QueueFull,
+                    TrySendError::Closed(_) => Error::QueueClosed,
+                })
+            }
+        }
+    }
+
+    fn queue(&self, id: Ulid, cancellation: Cancellation) {
+        let mut inner = self.lock();
+        inner.statuses.insert(id, (SubmissionStatus::Queued, None));
+        inner.cancellations.insert(id, cancellation);
+    }
+
+    /// Cancels the submission with `id` unless it has finished,
+    /// returning whether there was one to cancel.
+    fn cancel(&self, id: Ulid) -> bool {
+        let cancellation = self.lock().cancellations.get(&id).cloned();
+        match cancellation {
+            Some(cancellation) => {
+                cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn forget_cancellation(&self, id: Ulid) {
+        self.lock().cancellations.remove(&id);
+    }
+
+    fn set(&self, id: Ulid, status: SubmissionStatus) {
+        self.lock().statuses.insert(id, (status, None));
+    }
+
+    fn finish(&self, id: Ulid, response: RunnerResponse) {
+        let mut inner = self.lock();
+        inner
+            .statuses
+            .insert(id, (SubmissionStatus::Finished, Some(response)));
+        inner.finished.push_back(id);
+        while inner.finished.len() > Self::MAX_FINISHED {
+            if let Some(oldest) = inner.finished.pop_front() {
+                inner.statuses.remove(&oldest);
+            }
+        }
+    }
+
+    fn remove(&self, id: Ulid) {
+        let mut inner = self.lock();
+        inner.statuses.remove(&id);
+        inner.cancellations.remove(&id);
+    }
+
+    fn get(&self, id: Ulid) -> Option<Submission> {
+        let inner = self.lock();
+        let (status, response) = inner.statuses.get(&id)?;
+        Some(Submission {
+            id: id.to_string(),
+            status: *status,
+            response: response.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> CheckedRequest {
+        CheckedRequest {
+            request: serde_json::from_value(serde_json::json!({
+                "lang": "python313",
+                "code": "print(input())",
+                "ms_time_limit": 1000,
+                "ms_wall_time_limit": null,
+                "memory_limit": "256M",
+                "test_cases": [],
+                "checker": null,
+                "interactor": null,
+            }))
+            .unwrap(),
+            files: Vec::new(),
+            problem_files: Vec::new(),
+            test_cases: Vec::new(),
+        }
+    }
+
+    fn status(submissions: &Submissions, id: Ulid) -> SubmissionStatus {
+        submissions.get(id).unwrap().status
+    }
+
+    #[test]
+    fn transitions() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let submissions = Submissions::default();
+        let (id, _) = submissions.push(&sender, request(), Reply::Poll).unwrap();
+        assert_eq!(status(&submissions, id), SubmissionStatus::Queued);
+        assert_eq!(receiver.try_recv().unwrap().id, id);
+
+        for (event, expected) in [
+            (RunEvent::Compiling, SubmissionStatus::Compiling),
+            (
+                RunEvent::Running { index: 0, total: 2 },
+                SubmissionStatus::Running,
+            ),
+            (
+                RunEvent::Stdout {
+                    index: 0,
+                    data: "1\n".to_string(),
+                },
+                SubmissionStatus::Running,
+            ),
+            (
+                RunEvent::Running { index: 1, total: 2 },
+                SubmissionStatus::Running,
+            ),
+        ] {
+            if let Some(status) = status_after(&event) {
+                submissions.set(id, status);
+            }
+            assert_eq!(status(&submissions, id), expected);
+        }
+
+        submissions.finish(id, RunnerResponse::internal_error());
+        submissions.forget_cancellation(id);
+        let finished = submissions.get(id).unwrap();
+        assert_eq!(finished.status, SubmissionStatus::Finished);
+        assert_eq!(finished.response, Some(RunnerResponse::internal_error()));
+        assert!(!submissions.cancel(id));
+    }
+
+    #[test]
+    fn queue_full() {
+        let (sender, receiver) = mpsc::channel(1);
+        let submissions = Submissions::default();
+        let (id, _) = submissions.push(&sender, request(), Reply::Poll).unwrap();
+        assert!(matches!(
+            submissions.push(&sender, request(), Reply::Poll),
+            Err(Error::QueueFull)
+        ));
+        // Only the queued submission is tracked.
+        assert_eq!(submissions.lock().statuses.len(), 1);
+        assert_eq!(submissions.lock().cancellations.len(), 1);
+        assert_eq!(status(&submissions, id), SubmissionStatus::Queued);
+
+        drop(receiver);
+        assert!(matches!(
+            submissions.push(&sender, request(), Reply::Poll),
+            Err(Error::QueueClosed)
+        ));
+    }
+
+    #[test]
+    fn cancel_queued() {
+        let (sender, mut receiver) = mpsc::channel(2);
+        let submissions = Submissions::default();
+        let (id, _) = submissions.push(&sender, request(), Reply::Poll).unwrap();
+        let (other, _) = submissions.push(&sender, request(), Reply::Poll).unwrap();
+
+        assert!(submissions.cancel(id));
+        assert!(!submissions.cancel(Ulid::new()));
+
+        let job = receiver.try_recv().unwrap();
+        assert_eq!(job.id, id);
+        assert!(job.cancellation.is_cancelled());
+        let job = receiver.try_recv().unwrap();
+        assert_eq!(job.id, other);
+        assert!(!job.cancellation.is_cancelled());
+    }
+
+    #[test]
+    fn stream_starts_with_id() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let (events, mut received) = mpsc::unbounded_channel();
+        let submissions = Submissions::default();
+        let (id, _) = submissions
+            .push(&sender, request(), Reply::Stream(events))
+            .unwrap();
+        assert_eq!(
+            received.try_recv().unwrap(),
+            RunEvent::Queued { id: id.to_string() }
+        );
+        assert_eq!(receiver.try_recv().unwrap().id, id);
+    }
+}
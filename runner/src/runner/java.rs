@@ -0,0 +1,114 @@
+/// The class name used when the source declares no public top-level class.
+pub const DEFAULT_CLASS: &str = "Main";
+
+/// The name of the first public top-level class, interface, enum or record in Java `code`,
+/// which the file has to be named after.
+///
+/// Comments, literals and anything inside braces are skipped.
+pub fn public_class(code: &str) -> Option<&str> {
+    let tokens = top_level_tokens(code);
+    tokens.iter().enumerate().find_map(|(index, &token)| {
+        if token != "public" {
+            return None;
+        }
+        let mut rest = tokens[index + 1..]
+            .iter()
+            .skip_while(|token| matches!(**token, "final" | "abstract" | "strictfp" | "sealed"));
+        match rest.next() {
+            Some(&("class" | "interface" | "enum" | "record")) => rest.next().copied(),
+            _ => None,
+        }
+    })
+}
+
+/// The identifiers and keywords of `code` outside braces, comments and literals.
+fn top_level_tokens(code: &str) -> Vec<&str> {
+    let bytes = code.as_bytes();
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'/' if bytes.get(index + 1) == Some(&b'/') => {
+                index = find_from(code, index, "\n").unwrap_or(bytes.len());
+            }
+            b'/' if bytes.get(index + 1) == Some(&b'*') => {
+                index = find_from(code, index + 2, "*/").map_or(bytes.len(), |end| end + 2);
+            }
+            b'"' if code[index..].starts_with("\"\"\"") => {
+                index = find_from(code, index + 3, "\"\"\"").map_or(bytes.len(), |end| end + 3);
+            }
+            quote @ (b'"' | b'\'') => {
+                index += 1;
+                while index < bytes.len() && bytes[index] != quote && bytes[index] != b'\n' {
+                    index += if bytes[index] == b'\\' { 2 } else { 1 };
+                }
+                index += 1;
+            }
+            byte if is_identifier(byte) => {
+                let start = index;
+                while index < bytes.len() && is_identifier(bytes[index]) {
+                    index += 1;
+                }
+                if depth == 0
+                    && let Some(token) = code.get(start..index)
+                {
+                    tokens.push(token);
+                }
+            }
+            byte => {
+                match byte {
+                    b'{' => depth += 1,
+                    b'}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                index += 1;
+            }
+        }
+    }
+    tokens
+}
+
+fn find_from(code: &str, from: usize, pattern: &str) -> Option<usize> {
+    code.get(from..)?.find(pattern).map(|found| from + found)
+}
+
+/// Non-ASCII bytes are taken as part of identifiers, which Java allows. `$` is not,
+/// though Java allows it too, so that a class name can be put into a shell command as is.
+fn is_identifier(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_class_name() {
+        assert_eq!(
+            public_class("import java.util.*;\npublic class Solution {\n}"),
+            Some("Solution")
+        );
+        assert_eq!(
+            public_class("public final class Main { public static class Inner {} }"),
+            Some("Main")
+        );
+        assert_eq!(
+            public_class("class Outer { public class Inner {} }\npublic class Main {}"),
+            Some("Main")
+        );
+        assert_eq!(
+            public_class(
+                "// public class Commented {}\n/* public class Block {} */\npublic record Point(int x) {}"
+            ),
+            Some("Point")
+        );
+        assert_eq!(
+            public_class("class Hidden { String s = \"public class Quoted\"; }"),
+            None
+        );
+        assert_eq!(public_class("public class Ünïcode {}"), Some("Ünïcode"));
+        assert_ne!(public_class("public class $(reboot) {}"), Some("$(reboot)"));
+        assert_eq!(public_class("public class Main$1 {}"), Some("Main"));
+    }
+}
@@ -1,97 +1,341 @@
 #![deny(clippy::panic)]
 
-use runner_schema::Language;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
 
-use crate::nsjail::NsJailBuilder;
+use runner_schema::{
+    Language,
+    memory::Memory,
+    time::MsTime,
+    web::{LanguageInfo, RunnerRequest},
+};
 
-mod go;
-mod python;
-mod rust;
+use crate::{Error, nsjail::NsJailBuilder, program::Limits};
+
+pub mod java;
+mod registry;
 
 pub struct Runners {
-    map: enum_table::EnumTable<Language, LangRunner, { Language::COUNT }>,
+    map: HashMap<Language, LangRunner>,
+    languages: Vec<LanguageInfo>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid language registry {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("language {0} is defined more than once")]
+    Duplicate(Language),
+    #[error("failed to set up language {lang}: {source}")]
+    Setup {
+        lang: Language,
+        source: std::io::Error,
+    },
 }
 
 impl Runners {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Result<Self, (&'static Language, Box<dyn std::error::Error>)> {
-        let map = enum_table::EnumTable::try_new_with_fn(lang_into_runner)?;
+    /// Loads the language registry at `path`, see `languages.toml`,
+    /// and prepares every language in it.
+    pub fn new(path: &Path) -> Result<Self, LoadError> {
+        let definitions = registry::load(path)?;
+
+        let mut map = HashMap::with_capacity(definitions.len());
+        let mut languages = Vec::with_capacity(definitions.len());
+        for definition in definitions {
+            let lang = definition.id.clone();
+            if map.contains_key(&lang) {
+                return Err(LoadError::Duplicate(lang));
+            }
+
+            let (info, lang_runner) =
+                definition
+                    .into_runner()
+                    .map_err(|source| LoadError::Setup {
+                        lang: lang.clone(),
+                        source,
+                    })?;
+            languages.push(info);
+            map.insert(lang, lang_runner);
+        }
+
+        Ok(Self { map, languages })
+    }
+
+    /// Fails with [`Error::UnknownLanguage`] if `lang` is not in the registry.
+    pub fn get(&self, lang: &Language) -> crate::Result<&LangRunner> {
+        self.map
+            .get(lang)
+            .ok_or_else(|| Error::UnknownLanguage(lang.clone()))
+    }
 
-        Ok(Self { map })
+    /// Fails with [`Error::UnknownLanguage`] if any program of `request` is in a language
+    /// that is not in the registry, so that it is refused before it is queued.
+    pub fn check(&self, request: &RunnerRequest) -> crate::Result<()> {
+        self.get(&request.lang)?
+            .compile_flags(&request.lang, &request.compile_options)?;
+        if let Some(checker) = &request.checker {
+            self.get(&checker.lang)?;
+        }
+        if let Some(interactor) = &request.interactor {
+            self.get(&interactor.lang)?;
+        }
+        Ok(())
     }
 
-    pub fn get(&self, lang: &Language) -> &LangRunner {
-        self.map.get(lang)
+    /// The languages in the registry, in the order they are defined.
+    pub fn languages(&self) -> &[LanguageInfo] {
+        &self.languages
     }
 }
 
 pub enum LangRunner {
     WithCompile {
-        file_name: &'static str,
-        compile_cmd: &'static str,
-        run_cmd: &'static str,
+        file_name: String,
+        compile_cmd: String,
+        run_cmd: String,
         option: LangRunnerOption,
     },
     WithoutCompile {
-        file_name: &'static str,
-        run_cmd: &'static str,
-        option: LangRunnerOption,
-    },
-    Inline {
-        run_cmd: fn(&str) -> String,
+        file_name: String,
+        run_cmd: String,
         option: LangRunnerOption,
     },
 }
 
-#[derive(Default)]
 pub struct LangRunnerOption {
-    pub more_compile: Option<fn(&mut NsJailBuilder)>,
-    pub more_run: Option<fn(&mut NsJailBuilder)>,
+    pub more_compile: SandboxExtra,
+    pub more_run: SandboxExtra,
+    /// Scales the time limits of the request, for languages that are slow to start or to run.
+    pub time_limit_multiplier: f64,
+    /// Added to the time limits after scaling them, for runtimes that take a while to start.
+    pub extra_time_limit: MsTime,
+    /// Added to the memory limit, for what a runtime uses on top of the program's own memory.
+    pub extra_memory_limit: Memory,
+    /// Replaces the default `RLIMIT_AS` of compiling and running,
+    /// for runtimes that reserve far more address space than they use.
+    pub address_space_limit: Option<Memory>,
+    /// The flags of every compile option a submission may choose, by name.
+    pub compile_options: BTreeMap<String, String>,
+    /// What the compile command writes next to the submission, where a leading `*`
+    /// matches any prefix, see [`LangRunnerOption::is_compile_output`].
+    pub compile_outputs: Vec<String>,
+    /// Replaces the compile command when the problem provides a grader.
+    pub grader_compile_cmd: Option<String>,
+    /// Replaces the run command when the problem provides a grader.
+    pub grader_run_cmd: Option<String>,
+}
+
+impl Default for LangRunnerOption {
+    fn default() -> Self {
+        Self {
+            more_compile: SandboxExtra::default(),
+            more_run: SandboxExtra::default(),
+            time_limit_multiplier: 1.0,
+            extra_time_limit: MsTime::new_ms(0),
+            extra_memory_limit: Memory::new_bytes(0),
+            address_space_limit: None,
+            compile_options: BTreeMap::new(),
+            compile_outputs: Vec::new(),
+            grader_compile_cmd: None,
+            grader_run_cmd: None,
+        }
+    }
+}
+
+impl LangRunnerOption {
+    /// The limits a program in this language actually runs with, given the requested `limits`.
+    pub fn adjust(&self, limits: Limits) -> Limits {
+        let time = |time: MsTime| {
+            time.scale(self.time_limit_multiplier)
+                .add_ms(self.extra_time_limit.as_ms())
+        };
+        Limits {
+            wall_time: time(limits.wall_time),
+            cpu_time: limits.cpu_time.map(time),
+            memory: limits.memory.add_bytes(self.extra_memory_limit.as_bytes()),
+        }
+    }
+
+    /// Whether compiling may write a file called `name` next to the submission.
+    pub fn is_compile_output(&self, name: &str) -> bool {
+        self.compile_outputs
+            .iter()
+            .any(|pattern| match pattern.strip_prefix('*') {
+                Some(suffix) => name.ends_with(suffix),
+                None => name == pattern,
+            })
+    }
+
+    pub fn apply_address_space_limit(&self, builder: &mut NsJailBuilder) {
+        if let Some(limit) = self.address_space_limit {
+            builder.address_space_limit(limit);
+        }
+    }
 }
 
-pub enum RunCommand {
-    WithCode { run_cmd: fn(&str) -> String },
-    Static { run_cmd: &'static str },
+/// Replaces every `{name}` in `template` with its value in `vars`.
+pub fn expand(template: &str, vars: &[(&str, &str)]) -> String {
+    vars.iter()
+        .fold(template.to_string(), |expanded, (name, value)| {
+            expanded.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+/// Environment variables and mounts a language needs on top of the default sandbox.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxExtra {
+    pub env: BTreeMap<String, String>,
+    pub mount_ro: Vec<String>,
+    pub mount_rw: Vec<String>,
+}
+
+impl SandboxExtra {
+    pub fn apply(&self, builder: &mut NsJailBuilder) {
+        for (key, value) in &self.env {
+            builder.env(key, value);
+        }
+        for path in &self.mount_ro {
+            builder.mount_ro(path);
+        }
+        for path in &self.mount_rw {
+            builder.mount_rw(path);
+        }
+    }
 }
 
 impl LangRunner {
-    pub fn file_name(&self) -> Option<&'static str> {
+    pub fn file_name(&self) -> &str {
         match self {
-            LangRunner::WithCompile { file_name, .. } => Some(file_name),
-            LangRunner::WithoutCompile { file_name, .. } => Some(file_name),
-            LangRunner::Inline { .. } => None,
+            LangRunner::WithCompile { file_name, .. } => file_name,
+            LangRunner::WithoutCompile { file_name, .. } => file_name,
         }
     }
 
-    pub fn compile_cmd(&self) -> Option<&'static str> {
+    /// The file the code of a submission is written to, with `{class}` filled in from `code`.
+    pub fn entry_point(&self, code: &str) -> String {
+        let class = java::public_class(code).unwrap_or(java::DEFAULT_CLASS);
+        expand(self.file_name(), &[("class", class)])
+    }
+
+    pub fn compile_cmd(&self) -> Option<&str> {
         match self {
             LangRunner::WithCompile { compile_cmd, .. } => Some(compile_cmd),
             LangRunner::WithoutCompile { .. } => None,
-            LangRunner::Inline { .. } => None,
         }
     }
 
-    pub fn run_cmd(&self) -> RunCommand {
+    pub fn run_cmd(&self) -> &str {
         match self {
-            LangRunner::WithCompile { run_cmd, .. } => RunCommand::Static { run_cmd },
-            LangRunner::WithoutCompile { run_cmd, .. } => RunCommand::Static { run_cmd },
-            LangRunner::Inline { run_cmd, .. } => RunCommand::WithCode { run_cmd: *run_cmd },
+            LangRunner::WithCompile { run_cmd, .. } => run_cmd,
+            LangRunner::WithoutCompile { run_cmd, .. } => run_cmd,
         }
     }
 
+    /// The compile command of a submission, the grader one if `graded` and the language has it.
+    pub fn compile_cmd_for(&self, graded: bool) -> Option<&str> {
+        self.option()
+            .grader_compile_cmd
+            .as_deref()
+            .filter(|_| graded)
+            .or(self.compile_cmd())
+    }
+
+    /// The run command of a submission, the grader one if `graded` and the language has it.
+    pub fn run_cmd_for(&self, graded: bool) -> &str {
+        self.option()
+            .grader_run_cmd
+            .as_deref()
+            .filter(|_| graded)
+            .unwrap_or(self.run_cmd())
+    }
+
+    /// The flags of the compile `options` of a submission in `lang`, joined by spaces,
+    /// which fill in `{options}` of the compile command.
+    ///
+    /// Fails with [`Error::UnknownCompileOption`] for any option the language does not declare.
+    pub fn compile_flags(&self, lang: &Language, options: &[String]) -> crate::Result<String> {
+        let compile_options = &self.option().compile_options;
+        options
+            .iter()
+            .map(|option| {
+                compile_options
+                    .get(option)
+                    .map(String::as_str)
+                    .ok_or_else(|| Error::UnknownCompileOption {
+                        lang: lang.clone(),
+                        option: option.clone(),
+                    })
+            })
+            .collect::<crate::Result<Vec<_>>>()
+            .map(|flags| flags.join(" "))
+    }
+
     pub fn option(&self) -> &LangRunnerOption {
         match self {
             LangRunner::WithCompile { option, .. } => option,
             LangRunner::WithoutCompile { option, .. } => option,
-            LangRunner::Inline { option, .. } => option,
         }
     }
 }
 
-pub(super) fn lang_into_runner(lang: &Language) -> Result<LangRunner, Box<dyn std::error::Error>> {
-    Ok(match lang {
-        Language::Rust1_82 => rust::rust(),
-        Language::Go1_23 => go::go()?,
-        Language::Python3_13 => python::python(),
-    })
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_flags() {
+        let lang = Language::new("rust182");
+        let lang_runner = LangRunner::WithCompile {
+            file_name: "main.rs".to_string(),
+            compile_cmd: "rustc {options} main.rs".to_string(),
+            run_cmd: "./main".to_string(),
+            option: LangRunnerOption {
+                compile_options: BTreeMap::from([
+                    ("debug".to_string(), "-C debug-assertions=on".to_string()),
+                    ("edition2021".to_string(), "--edition 2021".to_string()),
+                ]),
+                ..Default::default()
+            },
+        };
+
+        let options = ["edition2021".to_string(), "debug".to_string()];
+        let flags = lang_runner.compile_flags(&lang, &options).unwrap();
+        assert_eq!(flags, "--edition 2021 -C debug-assertions=on");
+        assert_eq!(
+            expand(lang_runner.compile_cmd().unwrap(), &[("options", &flags)]),
+            "rustc --edition 2021 -C debug-assertions=on main.rs"
+        );
+
+        let options = ["-C link-arg=evil".to_string()];
+        assert!(matches!(
+            lang_runner.compile_flags(&lang, &options),
+            Err(Error::UnknownCompileOption { .. })
+        ));
+    }
+
+    #[test]
+    fn grader_commands() {
+        let lang_runner = LangRunner::WithoutCompile {
+            file_name: "main.py".to_string(),
+            run_cmd: "python main.py".to_string(),
+            option: LangRunnerOption {
+                grader_run_cmd: Some("python grader.py".to_string()),
+                ..Default::default()
+            },
+        };
+        assert_eq!(lang_runner.run_cmd_for(false), "python main.py");
+        assert_eq!(lang_runner.run_cmd_for(true), "python grader.py");
+        assert_eq!(lang_runner.compile_cmd_for(true), None);
+    }
 }
@@ -0,0 +1,224 @@
+use std::{collections::BTreeMap, path::Path};
+
+use runner_schema::{Language, memory::Memory, time::MsTime, web::LanguageInfo};
+
+use crate::{
+    env::{PERMISSION_ID, SH_CMD},
+    lang::LangExt,
+    nsjail::NsJailBuilder,
+};
+
+use super::{LangRunner, LangRunnerOption, LoadError, SandboxExtra};
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Registry {
+    language: Vec<LanguageDefinition>,
+}
+
+/// One `[[language]]` of the registry.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct LanguageDefinition {
+    pub id: Language,
+    name: String,
+    file_name: String,
+    compile_cmd: Option<String>,
+    run_cmd: String,
+    #[serde(default)]
+    compile_outputs: Vec<String>,
+    grader_compile_cmd: Option<String>,
+    grader_run_cmd: Option<String>,
+    #[serde(default)]
+    compile: SandboxExtra,
+    #[serde(default)]
+    run: SandboxExtra,
+    #[serde(default = "default_time_limit_multiplier")]
+    time_limit_multiplier: f64,
+    #[serde(default)]
+    extra_time_limit_ms: u64,
+    extra_memory_limit: Option<Memory>,
+    address_space_limit: Option<Memory>,
+    #[serde(default)]
+    compile_options: BTreeMap<String, String>,
+    warm_up: Option<WarmUp>,
+}
+
+fn default_time_limit_multiplier() -> f64 {
+    1.0
+}
+
+/// A program compiled once on startup, so that submissions find the caches filled.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WarmUp {
+    file_name: String,
+    code: String,
+    cmd: String,
+}
+
+pub(super) fn load(path: &Path) -> Result<Vec<LanguageDefinition>, LoadError> {
+    let text = std::fs::read_to_string(path).map_err(|source| LoadError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse(&text).map_err(|source| LoadError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn parse(text: &str) -> Result<Vec<LanguageDefinition>, toml::de::Error> {
+    Ok(toml::from_str::<Registry>(text)?.language)
+}
+
+impl LanguageDefinition {
+    /// Creates the writable directories of the language and warms it up.
+    pub(super) fn into_runner(self) -> std::io::Result<(LanguageInfo, LangRunner)> {
+        if !self.time_limit_multiplier.is_finite() || self.time_limit_multiplier <= 0.0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "time_limit_multiplier must be positive, got {}",
+                    self.time_limit_multiplier
+                ),
+            ));
+        }
+
+        for dir in self.compile.mount_rw.iter().chain(&self.run.mount_rw) {
+            std::fs::create_dir_all(dir)?;
+            std::os::unix::fs::chown(dir, Some(PERMISSION_ID), Some(PERMISSION_ID))?;
+        }
+
+        if let Some(warm_up) = &self.warm_up {
+            warm_up.run(&self.id, &self.compile, self.address_space_limit)?;
+        }
+
+        let info = LanguageInfo {
+            id: self.id,
+            name: self.name,
+            compile_options: self.compile_options.keys().cloned().collect(),
+        };
+        let option = LangRunnerOption {
+            more_compile: self.compile,
+            more_run: self.run,
+            time_limit_multiplier: self.time_limit_multiplier,
+            extra_time_limit: MsTime::new_ms(self.extra_time_limit_ms),
+            extra_memory_limit: self.extra_memory_limit.unwrap_or(Memory::new_bytes(0)),
+            address_space_limit: self.address_space_limit,
+            compile_options: self.compile_options,
+            compile_outputs: self.compile_outputs,
+            grader_compile_cmd: self.grader_compile_cmd,
+            grader_run_cmd: self.grader_run_cmd,
+        };
+        let lang_runner = match self.compile_cmd {
+            Some(compile_cmd) => LangRunner::WithCompile {
+                file_name: self.file_name,
+                compile_cmd,
+                run_cmd: self.run_cmd,
+                option,
+            },
+            None => LangRunner::WithoutCompile {
+                file_name: self.file_name,
+                run_cmd: self.run_cmd,
+                option,
+            },
+        };
+
+        Ok((info, lang_runner))
+    }
+}
+
+impl WarmUp {
+    fn run(
+        &self,
+        lang: &Language,
+        more_compile: &SandboxExtra,
+        address_space_limit: Option<Memory>,
+    ) -> std::io::Result<()> {
+        let temp_dir = std::env::temp_dir();
+        std::fs::write(temp_dir.join(&self.file_name), &self.code)?;
+
+        let mut builder = NsJailBuilder::new();
+        builder
+            .proc_writable(true)
+            .arg("--rlimit_fsize")
+            .arg("100")
+            .arg("--rlimit_nofile")
+            .arg("128")
+            .cwd(&temp_dir)
+            .env("PATH", &lang.bin_path())
+            .mount_ro(&lang.runner_path())
+            .tmpfsmount("/tmp", Memory::new_megabytes(512))
+            .writable();
+        more_compile.apply(&mut builder);
+        if let Some(limit) = address_space_limit {
+            builder.address_space_limit(limit);
+        }
+
+        let output = builder
+            .build()
+            .arg(SH_CMD)
+            .arg("-c")
+            .arg(&self.cmd)
+            .output()?;
+
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "Failed to warm up: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_registry() {
+        let definitions = parse(include_str!("../../languages.toml")).unwrap();
+        let go = definitions
+            .iter()
+            .find(|definition| definition.id == Language::new("go123"))
+            .unwrap();
+        assert_eq!(go.compile.env["GOCACHE"], "/go-cache");
+        assert!(go.warm_up.is_some());
+
+        let python = definitions
+            .iter()
+            .find(|definition| definition.id == Language::new("python313"))
+            .unwrap();
+        assert!(python.compile_cmd.is_some());
+        assert_eq!(python.time_limit_multiplier, 1.0);
+        assert_eq!(python.grader_run_cmd.as_deref(), Some("python grader.py"));
+        assert_eq!(python.compile_outputs, ["__pycache__"]);
+
+        let java = definitions
+            .iter()
+            .find(|definition| definition.id == Language::new("java21"))
+            .unwrap();
+        assert_eq!(java.extra_memory_limit, Some(Memory::new_megabytes(64)));
+        assert_eq!(java.address_space_limit, Some(Memory::new_megabytes(65536)));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_fields() {
+        assert!(
+            parse(
+                r#"
+                [[language]]
+                id = "c"
+                name = "C"
+                file_name = "main.c"
+                run_cmd = "./main"
+                compile_command = "gcc main.c"
+                "#
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+use runner_schema::state::{RuntimeErrorKind, Signal};
+
+use crate::nsjail::NsJailLog;
+
+/// How a sandboxed program that did not exit successfully ended.
+pub struct Termination {
+    pub exit_code: i32,
+    pub signal: Option<Signal>,
+    pub kind: RuntimeErrorKind,
+}
+
+impl Termination {
+    /// Prefers the signal nsjail logged, since `status` is only that of the
+    /// wrapper around it. Failing that, a signal is recovered from the wrapper's
+    /// status, where the shell convention of `128 + signal` is taken as one
+    /// unless nsjail logged that the process exited by itself.
+    pub fn new(status: ExitStatus, log: &NsJailLog) -> Self {
+        let number = log.signal.or_else(|| status.signal()).or_else(|| {
+            status
+                .code()
+                .filter(|code| *code > 128 && log.exit_status.is_none())
+                .map(|code| code - 128)
+        });
+
+        let kind = match number {
+            _ if log.time_limit_exceeded => RuntimeErrorKind::KilledByLimit,
+            Some(libc::SIGSEGV | libc::SIGBUS) => RuntimeErrorKind::SegmentationFault,
+            Some(libc::SIGFPE) => RuntimeErrorKind::FloatingPointException,
+            Some(libc::SIGABRT) => RuntimeErrorKind::Abort,
+            Some(libc::SIGKILL | libc::SIGXCPU | libc::SIGXFSZ) => RuntimeErrorKind::KilledByLimit,
+            Some(_) => RuntimeErrorKind::Signaled,
+            None => RuntimeErrorKind::NonZeroExit,
+        };
+
+        Self {
+            exit_code: status
+                .code()
+                .unwrap_or_else(|| 128 + number.unwrap_or(libc::SIGKILL)),
+            signal: number.map(|number| Signal {
+                number,
+                name: signal_name(number),
+            }),
+            kind,
+        }
+    }
+}
+
+pub fn signal_name(number: i32) -> String {
+    let name = match number {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGSYS => "SIGSYS",
+        _ => return format!("SIG{number}"),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exited(code: i32) -> ExitStatus {
+        ExitStatus::from_raw(code << 8)
+    }
+
+    fn logged(signal: i32) -> NsJailLog {
+        NsJailLog {
+            signal: Some(signal),
+            exit_status: None,
+            time_limit_exceeded: false,
+        }
+    }
+
+    #[test]
+    fn signals() {
+        for (signal, kind) in [
+            (libc::SIGSEGV, RuntimeErrorKind::SegmentationFault),
+            (libc::SIGBUS, RuntimeErrorKind::SegmentationFault),
+            (libc::SIGFPE, RuntimeErrorKind::FloatingPointException),
+            (libc::SIGABRT, RuntimeErrorKind::Abort),
+            (libc::SIGKILL, RuntimeErrorKind::KilledByLimit),
+            (libc::SIGPIPE, RuntimeErrorKind::Signaled),
+        ] {
+            let termination = Termination::new(exited(1), &logged(signal));
+            assert_eq!(termination.kind, kind, "{}", signal_name(signal));
+            assert_eq!(termination.signal.map(|signal| signal.number), Some(signal));
+
+            let termination = Termination::new(ExitStatus::from_raw(signal), &NsJailLog::default());
+            assert_eq!(termination.kind, kind, "{}", signal_name(signal));
+            assert_eq!(termination.exit_code, 128 + signal);
+        }
+    }
+
+    #[test]
+    fn time_limit() {
+        let log = NsJailLog {
+            signal: Some(libc::SIGSEGV),
+            exit_status: None,
+            time_limit_exceeded: true,
+        };
+        let termination = Termination::new(exited(1), &log);
+        assert_eq!(termination.kind, RuntimeErrorKind::KilledByLimit);
+    }
+
+    #[test]
+    fn exit_codes() {
+        let termination = Termination::new(exited(128 + libc::SIGSEGV), &NsJailLog::default());
+        assert_eq!(termination.kind, RuntimeErrorKind::SegmentationFault);
+        assert_eq!(termination.exit_code, 128 + libc::SIGSEGV);
+        assert_eq!(termination.signal.unwrap().name, "SIGSEGV");
+
+        for code in [1, 128] {
+            let termination = Termination::new(exited(code), &NsJailLog::default());
+            assert_eq!(termination.kind, RuntimeErrorKind::NonZeroExit);
+            assert_eq!(termination.exit_code, code);
+            assert!(termination.signal.is_none());
+        }
+    }
+
+    #[test]
+    fn exited_above_128() {
+        let log = NsJailLog {
+            exit_status: Some(139),
+            ..NsJailLog::default()
+        };
+        let termination = Termination::new(exited(139), &log);
+        assert_eq!(termination.kind, RuntimeErrorKind::NonZeroExit);
+        assert_eq!(termination.exit_code, 139);
+        assert!(termination.signal.is_none());
+    }
+}
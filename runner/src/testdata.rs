@@ -0,0 +1,303 @@
+//! Test data uploaded once and referred to by its SHA-256 from any number of requests,
+//! so that large inputs need not be sent inline every time.
+
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use runner_schema::{
+    memory::Memory,
+    web::{TestCase, TestData, TestDataInfo},
+};
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result, env::RunnerOption};
+
+/// The store under [`RunnerOption::testdata_path`], one file per hash.
+#[derive(Clone, Copy)]
+pub struct TestDataStore<'a> {
+    dir: &'a Path,
+    max_size: Memory,
+}
+
+impl<'a> TestDataStore<'a> {
+    /// Uploads are written next to the data under this suffix until their hash is checked.
+    const PARTIAL: &'static str = ".part";
+
+    pub fn new(option: &'a RunnerOption) -> Self {
+        Self {
+            dir: &option.testdata_path,
+            max_size: option.max_testdata_megabytes,
+        }
+    }
+
+    /// Creates the directory of the store and removes uploads a previous process left unfinished.
+    pub fn setup(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(self.dir)?;
+        for entry in std::fs::read_dir(self.dir)? {
+            let path = entry?.path();
+            if path.to_string_lossy().ends_with(Self::PARTIAL) {
+                log::info!("Removing unfinished upload {}", path.display());
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts storing data that is expected to hash to `sha256`.
+    pub fn upload(&self, sha256: &str) -> Result<Upload> {
+        let path = self.dir.join(check_hash(sha256)?);
+        let partial = self
+            .dir
+            .join(format!("{sha256}.{}{}", ulid::Ulid::new(), Self::PARTIAL));
+        Ok(Upload {
+            file: File::create(&partial)?,
+            hasher: Sha256::new(),
+            expected: sha256.to_string(),
+            remaining: self.max_size.as_bytes(),
+            limit: self.max_size,
+            partial,
+            path,
+        })
+    }
+
+    /// Every stored entry, in no particular order.
+    pub fn list(&self) -> Result<Vec<TestDataInfo>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(self.dir)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if check_hash(&name).is_ok() {
+                entries.push(TestDataInfo {
+                    sha256: name,
+                    bytes: entry.metadata()?.len(),
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Removes the data stored as `sha256`, returning whether there was any.
+    ///
+    /// Runs that already opened it still read it to the end.
+    pub fn delete(&self, sha256: &str) -> Result<bool> {
+        match std::fs::remove_file(self.dir.join(check_hash(sha256)?)) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Where `data` is read from, failing with [`Error::UnknownTestData`] if it is not stored
+    /// and with [`Error::InvalidData`] if inline data is not valid base64.
+    pub fn resolve(&self, data: TestData) -> Result<Resolved> {
+        match data {
+            TestData::Inline(data) => data
+                .into_bytes()
+                .map(Resolved::Bytes)
+                .map_err(|err| Error::InvalidData(format!("not valid base64: {err}"))),
+            TestData::Stored { sha256 } => {
+                let file = match File::open(self.dir.join(check_hash(&sha256)?)) {
+                    Ok(file) => file,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                        return Err(Error::UnknownTestData(sha256));
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+                if !file.metadata()?.is_file() {
+                    return Err(Error::UnknownTestData(sha256));
+                }
+                Ok(Resolved::File(file))
+            }
+        }
+    }
+
+    /// The input and expected output of `test_case`.
+    pub fn resolve_test_case(&self, test_case: TestCase) -> Result<ResolvedTestCase> {
+        Ok(ResolvedTestCase {
+            input: self.resolve(test_case.stdin)?,
+            answer: test_case
+                .expected_output
+                .map(|answer| self.resolve(answer))
+                .transpose()?,
+        })
+    }
+}
+
+/// An upload in progress, removed unless [`Upload::finish`] succeeds.
+pub struct Upload {
+    file: File,
+    hasher: Sha256,
+    expected: String,
+    remaining: u64,
+    limit: Memory,
+    partial: PathBuf,
+    path: PathBuf,
+}
+
+impl Upload {
+    pub fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.remaining = self
+            .remaining
+            .checked_sub(chunk.len() as u64)
+            .ok_or(Error::TestDataTooLarge(self.limit))?;
+        self.hasher.update(chunk);
+        self.file.write_all(chunk)?;
+        Ok(())
+    }
+
+    /// Stores the data if it hashes to what it was uploaded as,
+    /// failing with [`Error::HashMismatch`] otherwise.
+    pub fn finish(mut self) -> Result<()> {
+        let actual = format!("{:x}", std::mem::take(&mut self.hasher).finalize());
+        if actual != self.expected {
+            return Err(Error::HashMismatch {
+                expected: self.expected.clone(),
+                actual,
+            });
+        }
+        self.file.sync_all()?;
+        std::fs::rename(&self.partial, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        // Already renamed away when the upload finished.
+        match std::fs::remove_file(&self.partial) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                log::warn!("Failed to remove {}: {}", self.partial.display(), err);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Test data ready to be read.
+pub enum Resolved {
+    Bytes(Vec<u8>),
+    /// Stored data, which is streamed rather than read into memory where it can be.
+    ///
+    /// It is opened as soon as it is resolved, so that a run still reads it
+    /// if it is deleted from the store while the run waits in the queue.
+    File(File),
+}
+
+impl Resolved {
+    /// Reads the data from the start, however much of it was read before.
+    pub fn reader(&self) -> Result<Box<dyn Read + Send + '_>> {
+        Ok(match self {
+            Resolved::Bytes(bytes) => Box::new(bytes.as_slice()),
+            Resolved::File(file) => Box::new(rewound(file)?),
+        })
+    }
+
+    /// Writes the data to `path`, copying stored data file to file.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        match self {
+            Resolved::Bytes(bytes) => std::fs::write(path, bytes)?,
+            Resolved::File(file) => {
+                std::io::copy(&mut rewound(file)?, &mut File::create(path)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The whole data, for comparing output against.
+    ///
+    /// Stored data is read into memory in full, so an expected output is limited
+    /// by the memory of the runner rather than streamed like an input.
+    pub fn read(&self) -> Result<Cow<'_, [u8]>> {
+        Ok(match self {
+            Resolved::Bytes(bytes) => Cow::Borrowed(bytes.as_slice()),
+            Resolved::File(file) => {
+                let mut bytes = Vec::new();
+                rewound(file)?.read_to_end(&mut bytes)?;
+                Cow::Owned(bytes)
+            }
+        })
+    }
+}
+
+/// `file` seeked back to its start. Every read of the same data goes through one handle,
+/// so reads must not overlap.
+fn rewound(file: &File) -> std::io::Result<&File> {
+    let mut file = file;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// The data of a [`TestCase`], see [`TestDataStore::resolve_test_case`].
+pub struct ResolvedTestCase {
+    pub input: Resolved,
+    pub answer: Option<Resolved>,
+}
+
+/// `sha256` if it is 64 lowercase hex digits, and so safe to use as a file name.
+fn check_hash(sha256: &str) -> Result<&str> {
+    if sha256.len() == 64
+        && sha256
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        Ok(sha256)
+    } else {
+        Err(Error::InvalidHash(sha256.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes() {
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(format!("{:x}", Sha256::digest(b"")), empty);
+        assert!(check_hash(empty).is_ok());
+        assert!(check_hash(&empty.to_uppercase()).is_err());
+        assert!(check_hash("../../etc/passwd").is_err());
+        assert!(check_hash(&empty[1..]).is_err());
+    }
+
+    #[test]
+    fn deleted_after_resolving() {
+        let dir = std::env::temp_dir().join(format!("runner-testdata-{}", ulid::Ulid::new()));
+        std::fs::create_dir(&dir).unwrap();
+        let store = TestDataStore {
+            dir: &dir,
+            max_size: Memory::new_megabytes(1),
+        };
+        let sha256 = format!("{:x}", Sha256::digest(b"1 2\n"));
+        let mut upload = store.upload(&sha256).unwrap();
+        upload.write(b"1 2\n").unwrap();
+        upload.finish().unwrap();
+
+        let stored = TestData::Stored {
+            sha256: sha256.clone(),
+        };
+        let resolved = store.resolve(stored.clone()).unwrap();
+        assert!(store.delete(&sha256).unwrap());
+        assert_eq!(resolved.read().unwrap().as_ref(), b"1 2\n");
+        // Read again from the start.
+        let mut input = String::new();
+        resolved
+            .reader()
+            .unwrap()
+            .read_to_string(&mut input)
+            .unwrap();
+        assert_eq!(input, "1 2\n");
+        assert!(matches!(
+            store.resolve(stored),
+            Err(Error::UnknownTestData(unknown)) if unknown == sha256
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,14 +1,115 @@
-use std::{path::Path, process::Command};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
 
 use runner_schema::{memory::Memory, time::MsTime};
 
-use crate::env::TIME_CMD;
+use crate::{cgroup::CGroup, env::TIME_CMD, nsjail::NsJailBuilder, workdir};
 
-pub struct GTime;
+/// What a finished run used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub memory: Memory,
+    /// Wall clock time.
+    pub time: MsTime,
+    /// User plus system CPU time.
+    pub cpu_time: MsTime,
+    /// Whether the kernel killed the run for going over its memory limit.
+    pub oom_killed: bool,
+}
+
+/// Measures what a sandboxed run used.
+///
+/// One meter measures the runs in one directory, one after another.
+pub trait Meter: Send + Sync {
+    /// Forgets the previous run and returns the sandbox to spawn the next one in.
+    fn start(&self) -> std::io::Result<NsJailBuilder>;
+
+    /// Reads what the run used once it has exited.
+    ///
+    /// `elapsed` is the wall clock time the caller measured around the run.
+    fn read(&self, elapsed: MsTime) -> std::io::Result<Usage>;
+}
+
+/// Which [`Meter`] runs are measured with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Measurement {
+    /// Read the cgroup nsjail puts the run in, see [`CGroup`].
+    #[default]
+    CGroup,
+    /// Wrap nsjail in GNU time, see [`GTime`].
+    GTime,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown measurement: {0}, expected cgroup or gtime")]
+pub struct UnknownMeasurement(String);
+
+impl FromStr for Measurement {
+    type Err = UnknownMeasurement;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cgroup" => Ok(Measurement::CGroup),
+            "gtime" => Ok(Measurement::GTime),
+            _ => Err(UnknownMeasurement(s.to_string())),
+        }
+    }
+}
+
+impl Measurement {
+    /// Prepares the host for this measurement,
+    /// falling back to [`Measurement::GTime`] if cgroups are not usable.
+    pub fn setup(self) -> Self {
+        match self {
+            Measurement::CGroup => match CGroup::setup() {
+                Ok(()) => Measurement::CGroup,
+                Err(err) => {
+                    log::warn!("cgroup v2 is not usable, falling back to GNU time: {}", err);
+                    Measurement::GTime
+                }
+            },
+            Measurement::GTime => Measurement::GTime,
+        }
+    }
+
+    /// A meter for runs in `dir`.
+    pub fn meter(self, dir: &Path) -> Box<dyn Meter> {
+        match self {
+            Measurement::CGroup => Box::new(CGroup::new()),
+            Measurement::GTime => Box::new(GTime::new(dir.to_path_buf())),
+        }
+    }
+}
+
+/// Measures with GNU time wrapped around nsjail, which writes [`GTime::TIME_TXT`]
+/// into the run's directory.
+///
+/// The submission can write to that directory too, so prefer [`CGroup`].
+pub struct GTime {
+    dir: PathBuf,
+}
+
+impl Meter for GTime {
+    fn start(&self) -> std::io::Result<NsJailBuilder> {
+        workdir::clear_report(&self.dir.join(Self::TIME_TXT))?;
+        Ok(NsJailBuilder::new_with(Self::new_cmd()))
+    }
+
+    fn read(&self, _elapsed: MsTime) -> std::io::Result<Usage> {
+        Self::read(&self.dir)
+    }
+}
 
 impl GTime {
     pub const TIME_TXT: &'static str = "time.txt";
 
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
     pub fn new_cmd() -> Command {
         let mut command = Command::new(TIME_CMD);
         Self::write_args(&mut command);
@@ -24,14 +125,18 @@ impl GTime {
         command
             .arg("--quiet")
             .arg("--format")
-            .arg("%M\n%E")
+            .arg("%M\n%E\n%U\n%S")
             .arg("--output")
             .arg(Self::TIME_TXT);
     }
 
-    pub fn read(parent_dir: impl AsRef<Path>) -> std::io::Result<(Memory, MsTime)> {
+    pub fn read(parent_dir: impl AsRef<Path>) -> std::io::Result<Usage> {
         let path = parent_dir.as_ref().join(Self::TIME_TXT);
         let time_txt = std::fs::read_to_string(path)?;
+        Self::parse(&time_txt)
+    }
+
+    fn parse(time_txt: &str) -> std::io::Result<Usage> {
         let mut lines = time_txt.lines();
         fn invalid_data(message: &str) -> std::io::Error {
             std::io::Error::new(std::io::ErrorKind::InvalidData, message)
@@ -50,6 +155,40 @@ impl GTime {
         let time =
             MsTime::from_str_mm_ss_ms(time).ok_or_else(|| invalid_data("Invalid time line"))?;
 
-        Ok((memory, time))
+        let mut cpu_time = MsTime::new_ms(0);
+        for name in ["user", "system"] {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid_data(&format!("Missing {name} time line")))?;
+            let line = MsTime::from_str_seconds(line)
+                .ok_or_else(|| invalid_data(&format!("Invalid {name} time line")))?;
+            cpu_time = cpu_time.add_ms(line.as_ms());
+        }
+
+        Ok(Usage {
+            memory,
+            time,
+            cpu_time,
+            oom_killed: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            GTime::parse("2048\n0:01.50\n1.20\n0.05\n").unwrap(),
+            Usage {
+                memory: Memory::new_megabytes(2),
+                time: MsTime::new_ms(1500),
+                cpu_time: MsTime::new_ms(1250),
+                oom_killed: false,
+            }
+        );
+        assert!(GTime::parse("2048\n0:01.50\n").is_err());
     }
 }
@@ -0,0 +1,95 @@
+use std::{ffi::CString, os::unix::ffi::OsStrExt, path::Path};
+
+use runner_schema::memory::Memory;
+
+use crate::{
+    Error, Result,
+    env::{RUNNING_PATH, RunnerOption},
+};
+
+/// Removes everything left under [`RUNNING_PATH`] by a previous process.
+///
+/// Interrupted runs are never resumed, and runs kept by [`RunnerOption::keep_failed_runs`]
+/// are expected to be looked at before the runner is restarted.
+pub fn sweep() -> std::io::Result<()> {
+    sweep_dir(Path::new(RUNNING_PATH))
+}
+
+fn sweep_dir(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        log::info!("Removing stale run directory {}", path.display());
+        remove(&path)?;
+    }
+    Ok(())
+}
+
+/// Removes the directory of a run, or anything else found in its place.
+pub fn remove(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// Removes a file a run reports through before the next run in the same directory,
+/// so that nothing left over from a previous run is read back as this one's.
+pub fn clear_report(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Fails with [`Error::InsufficientDisk`] when less than
+/// [`RunnerOption::min_free_disk_megabytes`] is free on the volume of [`RUNNING_PATH`].
+pub fn check_free_space(option: &RunnerOption) -> Result<()> {
+    let available = available_space(Path::new(RUNNING_PATH))?;
+    if available < option.min_free_disk_megabytes {
+        return Err(Error::InsufficientDisk {
+            available,
+            required: option.min_free_disk_megabytes,
+        });
+    }
+    Ok(())
+}
+
+/// What an unprivileged user may still write to the volume of `path`.
+fn available_space(path: &Path) -> std::io::Result<Memory> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: statvfs is plain data, for which all zeroes is valid.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL terminated and `stat` is a valid statvfs.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(Memory::new_bytes(
+        stat.f_bavail.saturating_mul(stat.f_frsize),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_stale() {
+        let root = std::env::temp_dir().join(format!("runner-sweep-{}", ulid::Ulid::new()));
+        let running = root.join("running");
+        let outside = root.join("outside");
+        std::fs::create_dir_all(running.join("run/submission")).unwrap();
+        std::fs::write(running.join("run/submission/main.py"), "").unwrap();
+        std::fs::write(running.join("file"), "").unwrap();
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(outside.join("kept"), "").unwrap();
+        std::os::unix::fs::symlink(&outside, running.join("link")).unwrap();
+
+        sweep_dir(&running).unwrap();
+        assert_eq!(std::fs::read_dir(&running).unwrap().count(), 0);
+        // Only the link is removed, not what it points to.
+        assert!(outside.join("kept").exists());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}